@@ -1,15 +1,35 @@
 #! /usr/bin/env bash
 
-if [ $# != 1 ]; then
-  echo Usage: run.sh OUTFILE
+if [ $# -lt 1 ]; then
+  echo "Usage: run.sh OUTFILE [OPTIONS]"
+  echo "OPTIONS are passed to the simulation; see cargo run -- --help"
   exit 1
 fi
 
 OUTFILE=$1
+shift
 
-mkdir -p tmp
-cargo run -q >tmp/$OUTFILE.dat 2>tmp/$OUTFILE.log
-gnuplot -e "infile='tmp/$OUTFILE.dat'; outfile='tmp/$OUTFILE.gif'" animate.plg 2>/dev/null
-
-echo "Output in tmp/$OUTFILE.(gif|dat|log)"
+# the output is named after the format chosen, and scenarios name their own
+FORMAT=gif
+SCENARIO=
+ARGS=("$@")
+for ((i = 0; i < ${#ARGS[@]}; i++)); do
+  case ${ARGS[i]} in
+    -f|--format) FORMAT=${ARGS[i+1]} ;;
+    -s|--scenario) SCENARIO=${ARGS[i+1]} ;;
+  esac
+done
 
+mkdir -p tmp
+if [ -n "$SCENARIO" ]; then
+  cargo run -q -- "$@" 2>tmp/$OUTFILE.log
+  echo "Output as given in $SCENARIO, log in tmp/$OUTFILE.log"
+else
+  case $FORMAT in
+    # a file per frame, tmp/$OUTFILE-0000.png and so on
+    png|ppm) OUTPUT=tmp/$OUTFILE; WRITTEN=tmp/$OUTFILE-*.$FORMAT ;;
+    *) OUTPUT=tmp/$OUTFILE.$FORMAT; WRITTEN=$OUTPUT ;;
+  esac
+  cargo run -q -- "$@" -o "$OUTPUT" 2>tmp/$OUTFILE.log
+  echo "Output in $WRITTEN, log in tmp/$OUTFILE.log"
+fi
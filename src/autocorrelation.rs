@@ -0,0 +1,199 @@
+use std::collections::{HashMap, VecDeque};
+use super::{Collision, CustomFloat, Estimate, Space, Time, Vector};
+
+/// The velocity autocorrelation function, C(τ) = <v(t)·v(t + τ)>, computed
+/// exactly from the collisions in a simulation, and the self-diffusion
+/// coefficient found from it by the Green–Kubo relation, D = ½∫C(τ)dτ.
+///
+/// Since velocities only change at collisions, v(t)·v(t + τ) is a step
+/// function of τ, which is integrated exactly over the time between
+/// collisions, up to `max_lag` multiples of `lag_step`, from time origins
+/// every `origin_every`. C(τ) itself is reported at those multiples.
+///
+/// Particles are identified by their ids, which must be unique. Velocities
+/// are taken to be constant between collisions, so the particles must not
+/// be under gravity.
+#[derive(Debug, Clone)]
+pub struct VelocityAutocorrelation {
+  lag_step: Time,
+  max_lag: usize,
+  origin_every: Time,
+  next_origin: Time,
+  velocities: HashMap<usize, Vector>,
+  origins: VecDeque<Origin>,
+  // the sum and number of products of velocities at each lag
+  sums: Vec<CustomFloat>,
+  counts: Vec<usize>,
+  // the Green–Kubo integral from each completed origin
+  integrals: Vec<CustomFloat>,
+}
+
+#[derive(Debug, Clone)]
+struct Origin {
+  t: Time,
+  velocities: HashMap<usize, Vector>,
+  // the sum of v(t)·v(t + τ) over the particles still present, and their number
+  sum: CustomFloat,
+  count: usize,
+  // the integral of their mean up to `until`
+  integral: CustomFloat,
+  until: Time,
+  // the mean of v(t)·v(t + τ) at each lag evaluated so far
+  values: Vec<CustomFloat>,
+}
+
+impl Origin {
+  fn mean(&self) -> CustomFloat {
+    if self.count > 0 { self.sum / self.count as CustomFloat } else { 0. }
+  }
+}
+
+impl VelocityAutocorrelation {
+  /// Starts with the velocities of the particles in `space`, at time `start`.
+  pub fn new<S: Space>(space: &S, start: Time, lag_step: Time, max_lag: usize,
+                       origin_every: Time) -> VelocityAutocorrelation {
+    assert!(lag_step.0 > 0. && origin_every.0 > 0., "lags and origins must be apart in time");
+    VelocityAutocorrelation {
+      lag_step,
+      max_lag,
+      origin_every,
+      next_origin: start,
+      velocities: space.particles().map(|p| (p.id, p.v)).collect(),
+      origins: VecDeque::new(),
+      sums: vec![0.; max_lag + 1],
+      counts: vec![0; max_lag + 1],
+      integrals: Vec::new(),
+    }
+  }
+
+  /// Returns C(τ) at each lag evaluated so far, averaged over the origins.
+  pub fn vacf(&self) -> Vec<(Time, CustomFloat)> {
+    self.sums.iter().zip(self.counts.iter()).enumerate()
+      .filter(|&(_, (_, &count))| count > 0)
+      .map(|(lag, (&sum, &count))| (Time(lag as CustomFloat * self.lag_step.0), sum / count as CustomFloat))
+      .collect()
+  }
+
+  /// Estimates the diffusion coefficient from the origins for which every
+  /// lag has been evaluated. The error assumes those origins are
+  /// uncorrelated, so `origin_every` should be longer than the time over
+  /// which C(τ) decays.
+  pub fn diffusion_coefficient(&self) -> Estimate {
+    Estimate::from_blocks(&self.integrals)
+  }
+
+  /// Evaluates every lag before `time`, and integrates up to it, using the
+  /// current velocities, which must be those up until `time`.
+  fn advance(&mut self, time: Time) {
+    while self.next_origin.0 < time.0 {
+      self.origins.push_back(Origin {
+        t: self.next_origin,
+        sum: self.velocities.values().map(|v| v * v).sum(),
+        count: self.velocities.len(),
+        integral: 0.,
+        until: self.next_origin,
+        velocities: self.velocities.clone(),
+        values: Vec::with_capacity(self.max_lag + 1),
+      });
+      self.next_origin = Time(self.next_origin.0 + self.origin_every.0);
+    }
+
+    let window = self.max_lag as CustomFloat * self.lag_step.0;
+    for origin in self.origins.iter_mut() {
+      loop {
+        let lag = origin.values.len();
+        if lag > self.max_lag || origin.t.0 + lag as CustomFloat * self.lag_step.0 >= time.0 {
+          break;
+        }
+        self.sums[lag] += origin.sum;
+        self.counts[lag] += origin.count;
+        origin.values.push(origin.mean());
+      }
+
+      let until = time.0.min(origin.t.0 + window);
+      if until > origin.until.0 {
+        origin.integral += origin.mean() * (until - origin.until.0);
+        origin.until = Time(until);
+      }
+    }
+
+    while self.origins.front().is_some_and(|o| o.values.len() > self.max_lag) {
+      let origin = self.origins.pop_front().unwrap();
+      self.integrals.push(0.5 * origin.integral);
+    }
+  }
+
+  /// Changes the velocity of the particle `id`, or removes it if `v` is
+  /// None, keeping the sum over the particles at each origin up to date.
+  fn set_velocity(&mut self, id: usize, v: Option<Vector>) {
+    let prev = match v {
+      Some(v) => self.velocities.insert(id, v),
+      None => self.velocities.remove(&id),
+    };
+    for origin in self.origins.iter_mut() {
+      if let Some(v0) = origin.velocities.get(&id) {
+        if let Some(ref prev) = prev {
+          origin.sum -= v0 * prev;
+          origin.count -= 1;
+        }
+        if let Some(ref v) = v {
+          origin.sum += v0 * v;
+          origin.count += 1;
+        }
+      }
+    }
+  }
+
+  /// Records a collision occurring at the absolute time `time`.
+  pub fn record(&mut self, time: Time, collision: &Collision) {
+    self.advance(time);
+    match *collision {
+      Collision::Free => (),
+      Collision::Wall { ref next, .. } |
+      Collision::Wrap { ref next, .. } |
+      Collision::Cell { ref next, .. } => self.set_velocity(next.id, Some(next.v)),
+      Collision::Exit { ref prev, .. } => self.set_velocity(prev.id, None),
+      Collision::Bounce { ref next1, ref next2, .. } => {
+        self.set_velocity(next1.id, Some(next1.v));
+        self.set_velocity(next2.id, Some(next2.v));
+      },
+    }
+  }
+
+  /// Reports the Green–Kubo diffusion coefficient, from the origins
+  /// completed by `now`.
+  pub fn report(&self, now: Time) -> Vec<(String, Estimate)> {
+    let mut vacf = self.clone();
+    vacf.advance(now);
+    vec![("green-kubo diffusion coefficient".to_string(), vacf.diffusion_coefficient())]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{Calendar, Time};
+  use super::super::fixtures::bouncing_between_walls;
+
+  #[test]
+  fn velocity_reverses_exactly_at_wall() {
+    let mut space = bouncing_between_walls();
+    let mut vacf = VelocityAutocorrelation::new(&space, Time(0.), Time(1.5), 6, Time(100.));
+
+    let mut now = Time(0.);
+    let mut calendar = Calendar::new(&space, now);
+    while now.0 < 10. {
+      let (event, collision) = calendar.pop(&space, now).unwrap();
+      now = Time(now.0 + collision.t().0);
+      vacf.record(now, &collision);
+      space = space.update(&collision).unwrap();
+      calendar.reschedule(&space, now, &event);
+    }
+
+    let values: Vec<CustomFloat> = vacf.vacf().iter().map(|&(_, c)| c).collect();
+    assert!(values == vec![1., 1., 1., -1., -1., -1., -1.], "{:?}", values);
+    // half the exact integral of C over 6 lags of 1.5: ½ (4 - 5)
+    let d = vacf.report(now)[0].1;
+    assert!((d.value + 0.5).abs() < 1e-12, "{:?}", d);
+  }
+}
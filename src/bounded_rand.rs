@@ -1,7 +1,42 @@
-use rand::{Rng};
+use std::io;
+use rand::{OsRng, Rng, SeedableRng, StdRng};
 
 use super::{CustomFloat, Particle, Vector};
 
+/// The generator which random initial states are drawn from. It is always
+/// seeded explicitly, so that any state can be reproduced from its seed.
+#[derive(Clone)]
+pub struct SeededRng {
+  seed: usize,
+  rng: StdRng,
+}
+
+impl SeededRng {
+  pub fn new(seed: usize) -> SeededRng {
+    SeededRng { seed, rng: SeedableRng::from_seed(&[seed][..]) }
+  }
+
+  /// Seeds the generator with a seed drawn from the OS, which `seed` returns
+  /// so that the run can be repeated.
+  pub fn from_os() -> io::Result<SeededRng> {
+    Ok(SeededRng::new(OsRng::new()?.gen()))
+  }
+
+  pub fn seed(&self) -> usize {
+    self.seed
+  }
+}
+
+impl Rng for SeededRng {
+  fn next_u32(&mut self) -> u32 {
+    self.rng.next_u32()
+  }
+
+  fn next_u64(&mut self) -> u64 {
+    self.rng.next_u64()
+  }
+}
+
 pub trait BoundedRand {
   /// pseudo-extends the Rand typeclass with the ability to specify bounds
   /// for the generated type. Bounds are assumed to be closed.
@@ -39,7 +74,8 @@ impl BoundedRand for Particle {
       x: BoundedRand::rand(rng, &lower.x, &upper.x),
       v: BoundedRand::rand(rng, &lower.v, &upper.v),
       r: BoundedRand::rand(rng, &lower.r, &upper.r),
-      m: BoundedRand::rand(rng, &lower.m, &upper.m)
+      m: BoundedRand::rand(rng, &lower.m, &upper.m),
+      displacement: Vector((0., 0.))
     }
   }
 }
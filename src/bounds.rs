@@ -1,15 +1,172 @@
-use super::{Collision, FloatOps, Particle, Time, Vector, custom_float};
+use std::str::FromStr;
+use super::{Collision, CustomFloat, FloatOps, Particle, Restitution, Time, Vector};
+use super::checkpoint::{Checkpoint, CheckpointError, CheckpointReader, CheckpointWriter};
+use super::particle::arrival_time;
+
+/// The height to which a reflecting side lifts a particle resting against
+/// it, since one left at rest would touch the side again at once.
+/// Within the tolerance to which particles are considered in contact.
+///
+/// The hop is not paid for: a particle of mass `m` which meets a side at
+/// exactly zero normal speed, under an acceleration `g` into it, leaves
+/// with the energy `m * g * RESTING_HOP` added. Elastic landings return it
+/// at the speed it left with, so this only recurs if it comes to rest
+/// against a side again.
+pub const RESTING_HOP: CustomFloat = 1e-5;
+
+/// How particles interact with a side of a `Bounds`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boundary {
+  /// Particles bounce elastically off the wall.
+  Reflecting,
+  /// Particles leaving through the side re-enter through the opposite side,
+  /// and interact with particles across the seam.
+  /// The opposite side must also be periodic.
+  Periodic,
+  /// Particles are removed as soon as they touch the wall.
+  Absorbing,
+  /// Particles are removed once their centers pass through the side.
+  Open,
+}
+
+impl Boundary {
+  /// Whether particles interact with the side on contact,
+  /// rather than once their centers reach it.
+  fn on_contact(&self) -> bool {
+    match *self {
+      Boundary::Reflecting | Boundary::Absorbing => true,
+      Boundary::Periodic | Boundary::Open => false,
+    }
+  }
+}
+
+impl FromStr for Boundary {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Boundary, String> {
+    match s {
+      "reflecting" => Ok(Boundary::Reflecting),
+      "periodic" => Ok(Boundary::Periodic),
+      "absorbing" => Ok(Boundary::Absorbing),
+      "open" => Ok(Boundary::Open),
+      _ => Err(format!("unknown boundary `{}`", s)),
+    }
+  }
+}
+
+/// A side of a `Bounds`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+  Left,
+  Right,
+  Bottom,
+  Top,
+}
 
 #[derive(Debug, Clone)]
 pub struct Bounds {
   top_right: Vector,
-  bottom_left: Vector
+  bottom_left: Vector,
+  // indexed by Side
+  sides: [Boundary; 4],
+  restitution: Restitution
 }
 
 impl Bounds {
   pub fn new(top_right: Vector, bottom_left: Vector) -> Bounds {
     Bounds::check_bounds(&top_right, &bottom_left);
-    Bounds { top_right: top_right, bottom_left: bottom_left }
+    Bounds {
+      top_right,
+      bottom_left,
+      sides: [Boundary::Reflecting; 4],
+      restitution: Restitution::elastic()
+    }
+  }
+
+  /// Makes collisions with reflecting walls inelastic.
+  pub fn with_restitution(self, restitution: Restitution) -> Bounds {
+    Bounds { restitution, .. self }
+  }
+
+  pub fn restitution(&self) -> &Restitution {
+    &self.restitution
+  }
+
+  /// Uses the same boundary on all four sides.
+  pub fn with_boundary(self, boundary: Boundary) -> Bounds {
+    self.with_sides(boundary, boundary, boundary, boundary)
+  }
+
+  /// Uses a different boundary on each side.
+  ///
+  /// # Panics
+  /// - if a periodic side is opposite a side which is not periodic
+  pub fn with_sides(self, left: Boundary, right: Boundary, bottom: Boundary, top: Boundary) -> Bounds {
+    assert!(
+      (left == Boundary::Periodic) == (right == Boundary::Periodic),
+      "periodic boundaries must be paired: left {:?}, right {:?}", left, right
+    );
+    assert!(
+      (bottom == Boundary::Periodic) == (top == Boundary::Periodic),
+      "periodic boundaries must be paired: bottom {:?}, top {:?}", bottom, top
+    );
+    Bounds { sides: [left, right, bottom, top], .. self }
+  }
+
+  pub fn boundary(&self, side: Side) -> Boundary {
+    self.sides[side as usize]
+  }
+
+  /// Returns whether the x and y axes, respectively, are periodic.
+  pub fn periodic(&self) -> (bool, bool) {
+    (self.boundary(Side::Left) == Boundary::Periodic,
+     self.boundary(Side::Bottom) == Boundary::Periodic)
+  }
+
+  /// Returns the offsets at which a particle's periodic images appear.
+  /// The first offset is always zero, i.e. the particle itself.
+  pub fn images(&self) -> Vec<Vector> {
+    let Vector((wx, wy)) = &self.top_right - &self.bottom_left;
+    let (px, py) = self.periodic();
+    let xs: &[CustomFloat] = if px { &[0., -wx, wx] } else { &[0.] };
+    let ys: &[CustomFloat] = if py { &[0., -wy, wy] } else { &[0.] };
+
+    let mut images = Vec::with_capacity(xs.len() * ys.len());
+    for &ox in xs {
+      for &oy in ys {
+        images.push(Vector((ox, oy)));
+      }
+    }
+    images
+  }
+
+  /// Returns the displacement from `b` to `a`. Along periodic axes,
+  /// this is the displacement to the nearest image of `a`.
+  pub fn separation(&self, a: &Vector, b: &Vector) -> Vector {
+    let Vector((dx, dy)) = a - b;
+    let Vector((wx, wy)) = &self.top_right - &self.bottom_left;
+    let (px, py) = self.periodic();
+    Vector((
+      if px { dx - wx * (dx / wx).round() } else { dx },
+      if py { dy - wy * (dy / wy).round() } else { dy }
+    ))
+  }
+
+  pub fn top_right(&self) -> &Vector {
+    &self.top_right
+  }
+
+  pub fn bottom_left(&self) -> &Vector {
+    &self.bottom_left
+  }
+
+  pub fn centre(&self) -> Vector {
+    (&self.top_right + &self.bottom_left).scale(0.5)
+  }
+
+  pub fn area(&self) -> CustomFloat {
+    let Vector((wx, wy)) = &self.top_right - &self.bottom_left;
+    wx * wy
   }
 
   fn check_bounds<'l>(top_right: &'l Vector, bottom_left: &'l Vector) {
@@ -31,46 +188,144 @@ impl Bounds {
     FloatOps(y) <= FloatOps(top)
   }
 
-  pub fn next_collision(&self, p: &Particle) -> Collision {
-    let Vector((mut vx, mut vy)) = p.v;
-
+  /// Computes the next interaction between the particle and a side
+  /// of the bounds, according to that side's `Boundary`.
+  /// The particle moves under the uniform acceleration `g`.
+  pub fn next_collision(&self, p: &Particle, g: &Vector) -> Collision {
+    let Vector((vx, vy)) = p.v;
     let Vector((xx, xy)) = p.x;
+    let Vector((gx, gy)) = *g;
     let Vector((rx, ty)) = self.top_right;
     let Vector((lx, by)) = self.bottom_left;
 
-    let dx = if vx.ge(&0.) { (xx - rx).abs() }
-      else { (xx - lx).abs() } - p.r;
-
-    let dy = if vy.ge(&0.) { (xy - ty).abs() }
-      else { (xy - by).abs() } - p.r;
+    let (dxt, side_x) = self.axis_time((Side::Left, Side::Right), xx, vx, gx, (lx, rx), p.r);
+    let (dyt, side_y) = self.axis_time((Side::Bottom, Side::Top), xy, vy, gy, (by, ty), p.r);
 
-    let dxt = (dx / vx).abs();
-    let dyt = (dy / vy).abs();
-    let t = if dxt.le(&dyt) {
-      vx *= -1.;
-      dxt
+    let (t, side) = if dxt.le(&dyt) {
+      (dxt, Some(side_x))
     } else if dyt.le(&dxt) { // protect against INF
-      vy *= -1.;
-      dyt
+      (dyt, Some(side_y))
     } else {
-      custom_float::INFINITY
+      (CustomFloat::INFINITY, None)
     };
 
-    if t.le(&0.) || !t.is_finite() {
-      error!("Bounds encountered an illegal state: t: {}, p: {:?}", t, p);
-      Collision::Free
-    } else {
-      let time = Time(t);
-      let p_next = {
-        let p_ev = p.evolve(time);
-        Particle { v: Vector((vx, vy)), .. p_ev }
-      };
-      Collision::Wall {
-        t: time,
-        prev: p.clone(),
-        next: p_next
+    let boundary = side.map(|s| self.boundary(s));
+    let on_contact = boundary.is_none_or(|b| b.on_contact());
+    // a particle at rest against the side it is pushed into touches it now
+    let resting = t == 0. && match side {
+      Some(Side::Left) | Some(Side::Right) => vx == 0.,
+      Some(Side::Bottom) | Some(Side::Top) => vy == 0.,
+      None => false
+    };
+    if (on_contact && t.le(&0.) && !resting) || !t.is_finite() {
+      if on_contact {
+        error!("Bounds encountered an illegal state: t: {}, p: {:?}", t, p);
       }
+      return Collision::Free;
+    }
+
+    let time = Time(t);
+    let side = side.unwrap();
+    let p_ev = p.evolve_under(time, g);
+    let Vector((xx, xy)) = p_ev.x;
+    let Vector((vx, vy)) = p_ev.v;
+    match self.boundary(side) {
+      Boundary::Reflecting => {
+        let reflect = |v: CustomFloat, a: CustomFloat| if v == 0. {
+          -a.signum() * (2. * a.abs() * RESTING_HOP).sqrt()
+        } else {
+          -v * self.restitution.at(v)
+        };
+        let v = match side {
+          Side::Left | Side::Right => Vector((reflect(vx, gx), vy)),
+          Side::Bottom | Side::Top => Vector((vx, reflect(vy, gy))),
+        };
+        Collision::Wall {
+          t: time,
+          prev: p.clone(),
+          next: Particle { v, .. p_ev }
+        }
+      },
+      Boundary::Periodic => {
+        let x_next = match side {
+          Side::Left => Vector((rx, xy)),
+          Side::Right => Vector((lx, xy)),
+          Side::Bottom => Vector((xx, ty)),
+          Side::Top => Vector((xx, by)),
+        };
+        Collision::Wrap {
+          t: time,
+          prev: p.clone(),
+          next: Particle { x: x_next, .. p_ev }
+        }
+      },
+      Boundary::Absorbing | Boundary::Open =>
+        Collision::Exit { t: time, prev: p.clone() }
+    }
+  }
+
+  /// Computes the time until a particle at `x`, moving at `v` with
+  /// acceleration `a` along an axis, reaches either of the given (low, high)
+  /// sides, lying at `edges` along that axis, and which side it reaches.
+  fn axis_time(&self, sides: (Side, Side), x: CustomFloat, v: CustomFloat, a: CustomFloat,
+               edges: (CustomFloat, CustomFloat), r: CustomFloat) -> (CustomFloat, Side) {
+    let (lo_side, hi_side) = sides;
+    let (lo, hi) = edges;
+    if a == 0. {
+      let (side, edge) = if v.ge(&0.) { (hi_side, hi) } else { (lo_side, lo) };
+      return (self.time_to(side, x, v, edge, r), side);
+    }
+
+    let reach = |side: Side| if self.boundary(side).on_contact() { r } else { 0. };
+    let t_lo = arrival_time(x, v, a, lo + reach(lo_side), -1.);
+    let t_hi = arrival_time(x, v, a, hi - reach(hi_side), 1.);
+    match (t_lo, t_hi) {
+      (Some(tl), Some(th)) if tl < th => (tl, lo_side),
+      (_, Some(th)) => (th, hi_side),
+      (Some(tl), None) => (tl, lo_side),
+      (None, None) => (CustomFloat::INFINITY, hi_side)
+    }
+  }
+
+  /// Computes the time until a particle at `x`, moving at `v` along an axis,
+  /// reaches the given side, which lies at `edge` along that axis.
+  fn time_to(&self, side: Side, x: CustomFloat, v: CustomFloat, edge: CustomFloat,
+             r: CustomFloat) -> CustomFloat {
+    if self.boundary(side).on_contact() {
+      (((x - edge).abs() - r) / v).abs()
+    } else if v == 0. {
+      CustomFloat::INFINITY
+    } else {
+      ((edge - x) / v).max(0.)
     }
   }
 }
 
+impl Checkpoint for Bounds {
+  fn save_to(&self, w: &mut CheckpointWriter) {
+    w.line("bounds").vector(&self.top_right).vector(&self.bottom_left);
+    for side in &self.sides {
+      side.save_to(w);
+    }
+    self.restitution.save_to(w);
+  }
+
+  fn load_from(r: &mut CheckpointReader) -> Result<Bounds, CheckpointError> {
+    r.expect("bounds")?;
+    let (top_right, bottom_left) = (r.vector()?, r.vector()?);
+    let Vector((dx, dy)) = &top_right - &bottom_left;
+    if !(dx > 0. && dy > 0.) {
+      return Err(CheckpointError::Malformed(
+        format!("box from {:?} to {:?} is empty", bottom_left, top_right)));
+    }
+    let sides = [Boundary::load_from(r)?, Boundary::load_from(r)?,
+                 Boundary::load_from(r)?, Boundary::load_from(r)?];
+    let restitution = Restitution::load_from(r)?;
+    Ok(Bounds {
+      top_right,
+      bottom_left,
+      sides,
+      restitution
+    })
+  }
+}
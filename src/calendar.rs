@@ -0,0 +1,209 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use super::{Collision, FloatOps, Space, Time};
+use super::checkpoint::{Checkpoint, CheckpointError, CheckpointReader, CheckpointWriter};
+
+/// Identifies a predicted collision by the indices of the particles
+/// involved, as they appear in `Space::particles`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Event {
+  Bounce(usize, usize),
+  Wall(usize),
+  Cell(usize),
+}
+
+impl Event {
+  /// Returns the indices of the particles involved in the event.
+  pub fn particles(&self) -> (usize, Option<usize>) {
+    match *self {
+      Event::Bounce(i, j) => (i, Some(j)),
+      Event::Wall(i) |
+      Event::Cell(i) => (i, None),
+    }
+  }
+}
+
+/// A prediction made on behalf of `owner`, valid as long as neither the owner
+/// nor the other particle involved has changed since it was scheduled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Scheduled {
+  t: FloatOps,
+  event: Event,
+  owner: usize,
+  counts: (usize, usize),
+}
+
+impl PartialOrd for Scheduled {
+  fn partial_cmp(&self, other: &Scheduled) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Scheduled {
+  // reversed, so that the BinaryHeap pops the earliest event first; every
+  // field is compared, so that ties are popped in the same order however
+  // the heap was built
+  fn cmp(&self, other: &Scheduled) -> Ordering {
+    other.t.cmp(&self.t)
+      .then_with(|| other.event.cmp(&self.event))
+      .then_with(|| other.owner.cmp(&self.owner))
+      .then_with(|| other.counts.cmp(&self.counts))
+  }
+}
+
+/// A persistent calendar of predicted collisions.
+///
+/// Each particle has its earliest collision scheduled at an absolute time.
+/// When a collision changes a particle, its invalidation counter is bumped
+/// and only its predictions are recomputed, rather than rescanning every
+/// pair in the `Space`. Stale predictions are discarded lazily when they
+/// reach the front of the queue.
+#[derive(Debug, Clone)]
+pub struct Calendar {
+  queue: BinaryHeap<Scheduled>,
+  counts: Vec<usize>,
+}
+
+impl Calendar {
+  pub fn new<S: Space>(space: &S, now: Time) -> Calendar {
+    let n = space.particles().len();
+    let mut calendar = Calendar {
+      queue: BinaryHeap::with_capacity(n),
+      counts: vec![0; n],
+    };
+    for i in 0..n {
+      calendar.schedule(space, now, i);
+    }
+    calendar
+  }
+
+  /// Returns the number of particles the calendar schedules.
+  pub fn particle_count(&self) -> usize {
+    self.counts.len()
+  }
+
+  /// Invalidates the predictions of the particles changed by `event`,
+  /// and schedules their next collisions within `space`, which must be the
+  /// state of the system immediately after `event`, at time `now`.
+  pub fn reschedule<S: Space>(&mut self, space: &S, now: Time, event: &Event) {
+    if space.particles().len() != self.counts.len() {
+      // a particle was removed, so the indices of the others have shifted
+      *self = Calendar::new(space, now);
+      return;
+    }
+
+    let (i, opt_j) = event.particles();
+    self.counts[i] += 1;
+    if let Some(j) = opt_j { self.counts[j] += 1; }
+
+    self.schedule(space, now, i);
+    if let Some(j) = opt_j { self.schedule(space, now, j); }
+  }
+
+  /// Removes the earliest valid event and returns it, along with the
+  /// collision it describes, or None if no further collisions will occur.
+  /// `space` must be the current state of the system at time `now`.
+  ///
+  /// The collision is recomputed from `space`, so that it is exactly the one
+  /// `Space::next_collision` would find.
+  pub fn pop<S: Space>(&mut self, space: &S, now: Time) -> Option<(Event, Collision)> {
+    while let Some(s) = self.queue.pop() {
+      let (i, opt_j) = s.event.particles();
+      let partner = if s.owner == i { opt_j } else { Some(i) };
+
+      if self.counts[s.owner] != s.counts.0 {
+        // the owner has already been rescheduled
+        continue;
+      } else if partner.is_some_and(|j| self.counts[j] != s.counts.1) {
+        // the partner changed, but the owner still needs a prediction
+        self.schedule(space, now, s.owner);
+      } else {
+        match space.collision(&s.event) {
+          // rounding error made the prediction vanish
+          Collision::Free => self.reschedule(space, now, &s.event),
+          coll => return Some((s.event, coll)),
+        }
+      }
+    }
+    None
+  }
+
+  fn schedule<S: Space>(&mut self, space: &S, now: Time, owner: usize) {
+    if let Some((Time(t), event)) = space.predict(owner) {
+      let (i, opt_j) = event.particles();
+      let partner = if owner == i { opt_j } else { Some(i) };
+      self.queue.push(Scheduled {
+        t: FloatOps(now.0 + t),
+        event,
+        owner,
+        counts: (self.counts[owner], partner.map_or(0, |j| self.counts[j])),
+      });
+    }
+  }
+}
+
+impl Checkpoint for Calendar {
+  fn save_to(&self, w: &mut CheckpointWriter) {
+    w.line("calendar").usize(self.counts.len()).usize(self.queue.len());
+    for &count in &self.counts {
+      w.usize(count);
+    }
+    for s in self.queue.iter() {
+      w.line("scheduled").float(s.t.0);
+      s.event.save_to(w);
+      w.usize(s.owner).usize(s.counts.0).usize(s.counts.1);
+    }
+  }
+
+  fn load_from(r: &mut CheckpointReader) -> Result<Calendar, CheckpointError> {
+    r.expect("calendar")?;
+    let (n, len) = (r.usize()?, r.usize()?);
+    let counts = (0..n).map(|_| r.usize()).collect::<Result<_, _>>()?;
+    let mut queue = Vec::with_capacity(len);
+    for _ in 0..len {
+      r.expect("scheduled")?;
+      let s = Scheduled {
+        t: FloatOps(r.float()?),
+        event: Event::load_from(r)?,
+        owner: r.usize()?,
+        counts: (r.usize()?, r.usize()?),
+      };
+      let (i, opt_j) = s.event.particles();
+      if s.t.0.is_nan() || i >= n || opt_j.is_some_and(|j| j >= n) || !(s.owner == i || Some(s.owner) == opt_j) {
+        return Err(CheckpointError::Malformed(format!("{:?} is not among {} particles", s, n)));
+      }
+      queue.push(s);
+    }
+    Ok(Calendar { queue: BinaryHeap::from(queue), counts })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{Particle, SeededRng, SpaceBox, Vector};
+
+  #[test]
+  fn matches_full_rescan() {
+    let min = Particle::new(0, Vector((-10., -10.)), Vector((-1., -1.)), 0.5, 1.);
+    let max = Particle::new(0, Vector((10., 10.)), Vector((1., 1.)), 0.5, 2.);
+    let mut rng = SeededRng::new(1234);
+    let mut space = SpaceBox::new_random(&mut rng, 40, min, max).unwrap();
+    let mut now = Time(0.);
+    let mut calendar = Calendar::new(&space, now);
+
+    for _ in 0..500 {
+      let expected = space.next_collision();
+      let (event, collision) = calendar.pop(&space, now).unwrap();
+      assert!(
+        collision == expected,
+        "{:?} did not equal {:?}",
+        collision,
+        expected);
+
+      space = space.update(&collision).unwrap();
+      now = Time(now.0 + collision.t().0);
+      calendar.reschedule(&space, now, &event);
+    }
+  }
+}
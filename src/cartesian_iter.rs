@@ -7,7 +7,7 @@ impl<'l, T> IntoIterator for Combination2<'l, T> {
   fn into_iter(self) -> Combination2Iter<'l, T> {
     let Combination2(vec) = self;
     Combination2Iter {
-      vec: vec,
+      vec,
       idx1: 0,
       idx2: 1,
     }
@@ -0,0 +1,271 @@
+use super::{Bounds, CustomFloat, FloatOps, Particle, Time, Vector};
+use super::checkpoint::{Checkpoint, CheckpointError, CheckpointReader, CheckpointWriter};
+use super::particle::arrival_time;
+
+/// A uniform grid over a `Bounds`, tracking which cell each particle is in.
+///
+/// Cells are at least `size` wide in both dimensions, so with `size` no
+/// smaller than the largest particle diameter, a particle can only collide
+/// with particles in its own or a neighbouring cell. Particles are indexed
+/// as they appear in `Space::particles`.
+///
+/// Under periodic boundaries, cells on opposite edges are neighbours.
+/// Particles crossing those edges are moved by the `SpaceBox` when it wraps
+/// them around, rather than by a crossing.
+#[derive(Debug, Clone)]
+pub struct CellList {
+  origin: Vector,
+  width: Vector,
+  dims: (usize, usize),
+  wrap: (bool, bool),
+  cells: Vec<Vec<usize>>,
+  cell_of: Vec<(usize, usize)>,
+}
+
+impl CellList {
+  pub fn new<'l, I>(bounds: &Bounds, size: CustomFloat, particles: I) -> CellList
+  where I: Iterator<Item=&'l Particle> {
+    let Vector((wx, wy)) = bounds.top_right() - bounds.bottom_left();
+    let count = |w: CustomFloat| if size > 0. { ((w / size).floor() as usize).max(1) } else { 1 };
+    let dims = (count(wx), count(wy));
+
+    let mut cell_list = CellList {
+      origin: *bounds.bottom_left(),
+      width: Vector((wx / dims.0 as CustomFloat, wy / dims.1 as CustomFloat)),
+      dims,
+      wrap: bounds.periodic(),
+      cells: vec![Vec::new(); dims.0 * dims.1],
+      cell_of: Vec::new(),
+    };
+    for p in particles {
+      cell_list.add_particle(&p.x);
+    }
+    cell_list
+  }
+
+  pub fn dims(&self) -> (usize, usize) {
+    self.dims
+  }
+
+  /// Returns the number of particles in the cells.
+  pub fn particle_count(&self) -> usize {
+    self.cell_of.len()
+  }
+
+  /// Returns the cell containing the `i`th particle.
+  pub fn cell(&self, i: usize) -> (usize, usize) {
+    self.cell_of[i]
+  }
+
+  /// Returns the indices of the particles in the same or neighbouring cells
+  /// as the `i`th particle, excluding `i` itself.
+  pub fn neighbours(&self, i: usize) -> Vec<usize> {
+    let mut ns = self.around(self.cell_of[i]);
+    ns.retain(|&j| j != i);
+    ns
+  }
+
+  /// Returns the indices of the particles in the same or neighbouring cells
+  /// as the position `x`.
+  pub fn near(&self, x: &Vector) -> Vec<usize> {
+    self.around(self.locate(x))
+  }
+
+  /// Returns the indices of the particles in `cell` and its neighbours.
+  fn around(&self, (cx, cy): (usize, usize)) -> Vec<usize> {
+    let xs = CellList::adjacent(cx, self.dims.0, self.wrap.0);
+    let ys = CellList::adjacent(cy, self.dims.1, self.wrap.1);
+    let mut ns = Vec::new();
+    for &y in &ys {
+      for &x in &xs {
+        ns.extend(&self.cells[x + y * self.dims.0]);
+      }
+    }
+    ns
+  }
+
+  /// Returns the distinct indices within one of `c` along an axis of `n` cells.
+  fn adjacent(c: usize, n: usize, wrap: bool) -> Vec<usize> {
+    let mut cs: Vec<usize> = if wrap {
+      vec![(c + n - 1) % n, c, (c + 1) % n]
+    } else {
+      (c.saturating_sub(1)..(c + 2).min(n)).collect()
+    };
+    cs.sort();
+    cs.dedup();
+    cs
+  }
+
+  /// Computes when the `i`th particle, `p`, moving under the uniform
+  /// acceleration `g`, will leave its cell, and the cell it will enter.
+  /// Returns None if it will stay in its cell until it hits a wall.
+  pub fn crossing(&self, i: usize, p: &Particle, g: &Vector) -> Option<(Time, (usize, usize))> {
+    let (cx, cy) = self.cell_of[i];
+    let Vector((x, y)) = p.x;
+    let Vector((vx, vy)) = p.v;
+    let Vector((gx, gy)) = *g;
+    let Vector((ox, oy)) = self.origin;
+    let Vector((wx, wy)) = self.width;
+
+    // time until a boundary of the cell, and the index
+    // of the cell across it, along a single axis
+    let axis = |x: CustomFloat, v: CustomFloat, a: CustomFloat, o: CustomFloat, w: CustomFloat,
+                c: usize, n: usize| {
+      let (lo, hi) = (o + c as CustomFloat * w, o + (c + 1) as CustomFloat * w);
+      if a != 0. && x > hi && c + 1 < n {
+        Some((0., c + 1))
+      } else if a != 0. && x < lo && c > 0 {
+        Some((0., c - 1))
+      } else if a != 0. {
+        // under acceleration, the particle may turn back before either boundary
+        let up = if c + 1 < n { arrival_time(x, v, a, hi, 1.).map(|t| (t, c + 1)) } else { None };
+        let down = if c > 0 { arrival_time(x, v, a, lo, -1.).map(|t| (t, c - 1)) } else { None };
+        up.into_iter().chain(down).min_by_key(|&(t, _)| FloatOps(t))
+      } else if v > 0. && c + 1 < n {
+        Some(((hi - x) / v, c + 1))
+      } else if v < 0. && c > 0 {
+        Some(((lo - x) / v, c - 1))
+      } else {
+        None
+      }
+    };
+
+    let next_x = axis(x, vx, gx, ox, wx, cx, self.dims.0).map(|(t, c)| (t, (c, cy)));
+    let next_y = axis(y, vy, gy, oy, wy, cy, self.dims.1).map(|(t, c)| (t, (cx, c)));
+
+    // rounding may leave a particle just past the boundary it crossed
+    next_x.into_iter().chain(next_y)
+      .min_by_key(|&(t, _)| FloatOps(t))
+      .map(|(t, cell)| (Time(t.max(0.)), cell))
+  }
+
+  /// Moves the `i`th particle into the given cell.
+  pub fn move_particle(&mut self, i: usize, cell: (usize, usize)) {
+    let (ox, oy) = self.cell_of[i];
+    self.cells[ox + oy * self.dims.0].retain(|&j| j != i);
+    self.cells[cell.0 + cell.1 * self.dims.0].push(i);
+    self.cell_of[i] = cell;
+  }
+
+  /// Adds a particle at `x`, indexed after the others.
+  pub fn add_particle(&mut self, x: &Vector) {
+    let cell = self.locate(x);
+    self.cells[cell.0 + cell.1 * self.dims.0].push(self.cell_of.len());
+    self.cell_of.push(cell);
+  }
+
+  /// Removes the `i`th particle, shifting the indices of those after it.
+  pub fn remove_particle(&mut self, i: usize) {
+    let (cx, cy) = self.cell_of.remove(i);
+    self.cells[cx + cy * self.dims.0].retain(|&j| j != i);
+    for cell in self.cells.iter_mut() {
+      for j in cell.iter_mut() {
+        if *j > i { *j -= 1; }
+      }
+    }
+  }
+
+  /// Returns the cell containing the given position.
+  pub fn locate(&self, x: &Vector) -> (usize, usize) {
+    let Vector((dx, dy)) = x - &self.origin;
+    let Vector((wx, wy)) = self.width;
+    let index = |d: CustomFloat, w: CustomFloat, n: usize|
+      ((d / w).max(0.).floor() as usize).min(n - 1);
+    (index(dx, wx, self.dims.0), index(dy, wy, self.dims.1))
+  }
+}
+
+impl Checkpoint for CellList {
+  // the cell of each particle is found from the cells,
+  // which are saved in order so that neighbours are too
+  fn save_to(&self, w: &mut CheckpointWriter) {
+    w.line("cells").vector(&self.origin).vector(&self.width)
+      .usize(self.dims.0).usize(self.dims.1)
+      .bool(self.wrap.0).bool(self.wrap.1)
+      .usize(self.cell_of.len());
+    for cell in &self.cells {
+      w.line("cell").usize(cell.len());
+      for &i in cell {
+        w.usize(i);
+      }
+    }
+  }
+
+  fn load_from(r: &mut CheckpointReader) -> Result<CellList, CheckpointError> {
+    r.expect("cells")?;
+    let (origin, width) = (r.vector()?, r.vector()?);
+    let dims = (r.usize()?, r.usize()?);
+    let wrap = (r.bool()?, r.bool()?);
+    let n = r.usize()?;
+    let Vector((wx, wy)) = width;
+    if dims.0 == 0 || dims.1 == 0 || !(wx > 0. && wy > 0.) {
+      return Err(CheckpointError::Malformed(
+        format!("{} by {} cells of size {:?}", dims.0, dims.1, width)));
+    }
+
+    let mut cells = Vec::with_capacity(dims.0 * dims.1);
+    let mut cell_of = vec![None; n];
+    for c in 0..dims.0 * dims.1 {
+      r.expect("cell")?;
+      let len = r.usize()?;
+      let mut cell = Vec::with_capacity(len);
+      for _ in 0..len {
+        let i = r.usize()?;
+        match cell_of.get_mut(i) {
+          Some(slot @ &mut None) => *slot = Some((c % dims.0, c / dims.0)),
+          _ => return Err(CheckpointError::Malformed(format!("particle {} is not in one cell", i))),
+        }
+        cell.push(i);
+      }
+      cells.push(cell);
+    }
+
+    Ok(CellList {
+      origin,
+      width,
+      dims,
+      wrap,
+      cells,
+      cell_of: cell_of.into_iter().enumerate()
+        .map(|(i, cell)| cell.ok_or_else(|| CheckpointError::Malformed(format!("particle {} is in no cell", i))))
+        .collect::<Result<_, _>>()?,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{Boundary, Bounds, Particle, Time, Vector};
+
+  const P: Particle = Particle::new(0, Vector((0.5, 0.5)), Vector((1., 0.25)), 0.5, 1.);
+
+  #[test]
+  fn cells_are_at_least_size_wide() {
+    let bounds = Bounds::new(Vector((5., 3.5)), Vector((0., 0.)));
+    let cells = CellList::new(&bounds, 1., [P].iter());
+    assert!(cells.dims() == (5, 3));
+    assert!(cells.cell(0) == (0, 0));
+  }
+
+  #[test]
+  fn particle_crosses_into_next_cell() {
+    let bounds = Bounds::new(Vector((4., 4.)), Vector((0., 0.)));
+    let cells = CellList::new(&bounds, 1., [P].iter());
+    let (Time(t), cell) = cells.crossing(0, &P, &Vector((0., 0.))).unwrap();
+    assert!((t - 0.5).abs() < 1e-10);
+    assert!(cell == (1, 0));
+  }
+
+  #[test]
+  fn periodic_cells_neighbour_across_edges() {
+    let bounds = Bounds::new(Vector((4., 4.)), Vector((0., 0.)))
+      .with_boundary(Boundary::Periodic);
+    let far = Particle { id: 1, x: Vector((3.5, 3.5)), .. P };
+    let mut cells = CellList::new(&bounds, 1., [P, far].iter());
+    assert!(cells.neighbours(0) == vec![1]);
+    cells.add_particle(&Vector((2.5, 2.5)));
+    let near = cells.near(&Vector((3.5, 0.5)));
+    assert!(near == vec![0, 1], "{:?} did not equal {:?}", near, vec![0, 1]);
+  }
+}
@@ -0,0 +1,363 @@
+use std::error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+use super::{Boundary, Collision, CustomFloat, Event, Particle, Restitution, Time, Vector};
+
+/// The version of the checkpoint format written by this crate.
+/// Checkpoints of any other version are rejected when read.
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+const MAGIC: &str = "particles-checkpoint";
+
+/// Why a checkpoint could not be read.
+#[derive(Debug)]
+pub enum CheckpointError {
+  Io(io::Error),
+  /// The checkpoint was written in a different version of the format.
+  Version(u32),
+  /// The checkpoint is not in the expected format, such as when it has
+  /// been truncated.
+  Malformed(String),
+}
+
+impl fmt::Display for CheckpointError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      CheckpointError::Io(ref e) => write!(f, "unable to read checkpoint: {}", e),
+      CheckpointError::Version(v) => write!(
+        f, "checkpoint has version {}, but only version {} is supported", v, CHECKPOINT_VERSION),
+      CheckpointError::Malformed(ref msg) => write!(f, "malformed checkpoint: {}", msg),
+    }
+  }
+}
+
+impl error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+  fn from(e: io::Error) -> CheckpointError {
+    CheckpointError::Io(e)
+  }
+}
+
+/// State which can be saved to a checkpoint and read back exactly.
+///
+/// Each value is written as a line starting with a label, followed by its
+/// fields, and by any nested values on lines of their own. Floats are
+/// written in their shortest form which parses back to the same value, so
+/// that a resumed simulation continues bit-for-bit.
+pub trait Checkpoint: Sized {
+  fn save_to(&self, w: &mut CheckpointWriter);
+  fn load_from(r: &mut CheckpointReader) -> Result<Self, CheckpointError>;
+}
+
+/// Writes `value` to `out` as a checkpoint, after a header with the version.
+pub fn write_checkpoint<T: Checkpoint>(out: &mut dyn Write, value: &T) -> io::Result<()> {
+  let mut w = CheckpointWriter { text: String::new() };
+  w.line(MAGIC).usize(CHECKPOINT_VERSION as usize);
+  value.save_to(&mut w);
+  w.text.push('\n');
+  out.write_all(w.text.as_bytes())
+}
+
+/// Reads a value written by `write_checkpoint` from `input`.
+pub fn read_checkpoint<T: Checkpoint>(input: &mut dyn Read) -> Result<T, CheckpointError> {
+  let mut text = String::new();
+  input.read_to_string(&mut text)?;
+  let mut r = CheckpointReader {
+    tokens: text.split_whitespace().map(|t| t.to_string()).collect(),
+    next: 0,
+  };
+
+  r.expect(MAGIC)?;
+  let version = r.parse("version")?;
+  if version != CHECKPOINT_VERSION {
+    return Err(CheckpointError::Version(version));
+  }
+  let value = T::load_from(&mut r)?;
+  match r.tokens.get(r.next) {
+    Some(token) => Err(CheckpointError::Malformed(format!("unexpected `{}` at end", token))),
+    None => Ok(value),
+  }
+}
+
+pub struct CheckpointWriter {
+  text: String,
+}
+
+impl CheckpointWriter {
+  /// Starts a new line with `label`.
+  pub fn line(&mut self, label: &str) -> &mut CheckpointWriter {
+    if !self.text.is_empty() { self.text.push('\n'); }
+    self.text.push_str(label);
+    self
+  }
+
+  pub fn word(&mut self, word: &str) -> &mut CheckpointWriter {
+    self.text.push(' ');
+    self.text.push_str(word);
+    self
+  }
+
+  pub fn float(&mut self, x: CustomFloat) -> &mut CheckpointWriter {
+    self.word(&format!("{:?}", x))
+  }
+
+  pub fn usize(&mut self, n: usize) -> &mut CheckpointWriter {
+    self.word(&n.to_string())
+  }
+
+  pub fn bool(&mut self, b: bool) -> &mut CheckpointWriter {
+    self.word(if b { "true" } else { "false" })
+  }
+
+  pub fn vector(&mut self, v: &Vector) -> &mut CheckpointWriter {
+    let &Vector((x, y)) = v;
+    self.float(x).float(y)
+  }
+}
+
+pub struct CheckpointReader {
+  tokens: Vec<String>,
+  next: usize,
+}
+
+impl CheckpointReader {
+  pub fn word(&mut self) -> Result<&str, CheckpointError> {
+    match self.tokens.get(self.next) {
+      Some(token) => {
+        self.next += 1;
+        Ok(token)
+      },
+      None => Err(CheckpointError::Malformed("unexpected end".to_string())),
+    }
+  }
+
+  /// Returns the next word without reading it.
+  pub fn peek(&self) -> Option<&str> {
+    self.tokens.get(self.next).map(|t| t.as_str())
+  }
+
+  /// Reads the label `expected`.
+  pub fn expect(&mut self, expected: &str) -> Result<(), CheckpointError> {
+    let word = self.word()?;
+    if word == expected {
+      Ok(())
+    } else {
+      Err(CheckpointError::Malformed(format!("expected `{}`, found `{}`", expected, word)))
+    }
+  }
+
+  /// Reads a value of any type which can be parsed from a single word,
+  /// described as `what` if it cannot.
+  pub fn parse<T: FromStr>(&mut self, what: &str) -> Result<T, CheckpointError> {
+    let word = self.word()?;
+    word.parse()
+      .map_err(|_| CheckpointError::Malformed(format!("expected {}, found `{}`", what, word)))
+  }
+
+  pub fn float(&mut self) -> Result<CustomFloat, CheckpointError> {
+    self.parse("a number")
+  }
+
+  pub fn usize(&mut self) -> Result<usize, CheckpointError> {
+    self.parse("a count")
+  }
+
+  pub fn bool(&mut self) -> Result<bool, CheckpointError> {
+    self.parse("true or false")
+  }
+
+  pub fn vector(&mut self) -> Result<Vector, CheckpointError> {
+    Ok(Vector((self.float()?, self.float()?)))
+  }
+}
+
+impl Checkpoint for Time {
+  fn save_to(&self, w: &mut CheckpointWriter) {
+    w.float(self.0);
+  }
+
+  fn load_from(r: &mut CheckpointReader) -> Result<Time, CheckpointError> {
+    Ok(Time(r.float()?))
+  }
+}
+
+impl Checkpoint for Restitution {
+  fn save_to(&self, w: &mut CheckpointWriter) {
+    w.float(self.e).float(self.cutoff);
+  }
+
+  fn load_from(r: &mut CheckpointReader) -> Result<Restitution, CheckpointError> {
+    let (e, cutoff) = (r.float()?, r.float()?);
+    if !(0. < e && e <= 1. && cutoff >= 0.) {
+      return Err(CheckpointError::Malformed(
+        format!("invalid restitution {} with cutoff {}", e, cutoff)));
+    }
+    Ok(Restitution::new(e, cutoff))
+  }
+}
+
+impl Checkpoint for Boundary {
+  fn save_to(&self, w: &mut CheckpointWriter) {
+    w.word(match *self {
+      Boundary::Reflecting => "reflecting",
+      Boundary::Periodic => "periodic",
+      Boundary::Absorbing => "absorbing",
+      Boundary::Open => "open",
+    });
+  }
+
+  fn load_from(r: &mut CheckpointReader) -> Result<Boundary, CheckpointError> {
+    match r.word()? {
+      "reflecting" => Ok(Boundary::Reflecting),
+      "periodic" => Ok(Boundary::Periodic),
+      "absorbing" => Ok(Boundary::Absorbing),
+      "open" => Ok(Boundary::Open),
+      word => Err(CheckpointError::Malformed(format!("unknown boundary `{}`", word))),
+    }
+  }
+}
+
+impl Checkpoint for Particle {
+  fn save_to(&self, w: &mut CheckpointWriter) {
+    w.line("particle").usize(self.id)
+      .vector(&self.x).vector(&self.v)
+      .float(self.r).float(self.m)
+      .vector(&self.displacement);
+  }
+
+  fn load_from(r: &mut CheckpointReader) -> Result<Particle, CheckpointError> {
+    r.expect("particle")?;
+    let p = Particle {
+      id: r.usize()?,
+      x: r.vector()?,
+      v: r.vector()?,
+      r: r.float()?,
+      m: r.float()?,
+      displacement: r.vector()?,
+    };
+    if !(p.r > 0. && p.m > 0.) {
+      return Err(CheckpointError::Malformed(format!("particle {} has radius {} and mass {}", p.id, p.r, p.m)));
+    }
+    Ok(p)
+  }
+}
+
+impl Checkpoint for Event {
+  fn save_to(&self, w: &mut CheckpointWriter) {
+    match *self {
+      Event::Bounce(i, j) => w.word("bounce").usize(i).usize(j),
+      Event::Wall(i) => w.word("wall").usize(i),
+      Event::Cell(i) => w.word("cell").usize(i),
+    };
+  }
+
+  fn load_from(r: &mut CheckpointReader) -> Result<Event, CheckpointError> {
+    match r.word()? {
+      "bounce" => Ok(Event::Bounce(r.usize()?, r.usize()?)),
+      "wall" => Ok(Event::Wall(r.usize()?)),
+      "cell" => Ok(Event::Cell(r.usize()?)),
+      word => Err(CheckpointError::Malformed(format!("unknown event `{}`", word))),
+    }
+  }
+}
+
+impl Checkpoint for Collision {
+  fn save_to(&self, w: &mut CheckpointWriter) {
+    w.line("collision");
+    match *self {
+      Collision::Free => { w.word("free"); },
+      Collision::Wall { t, ref prev, ref next } => {
+        w.word("wall").float(t.0);
+        prev.save_to(w);
+        next.save_to(w);
+      },
+      Collision::Wrap { t, ref prev, ref next } => {
+        w.word("wrap").float(t.0);
+        prev.save_to(w);
+        next.save_to(w);
+      },
+      Collision::Exit { t, ref prev } => {
+        w.word("exit").float(t.0);
+        prev.save_to(w);
+      },
+      Collision::Bounce { t, ref prev1, ref prev2, ref next1, ref next2 } => {
+        w.word("bounce").float(t.0);
+        for p in &[prev1, prev2, next1, next2] {
+          p.save_to(w);
+        }
+      },
+      Collision::Cell { t, ref prev, ref next, cell } => {
+        w.word("cell").float(t.0).usize(cell.0).usize(cell.1);
+        prev.save_to(w);
+        next.save_to(w);
+      },
+    }
+  }
+
+  fn load_from(r: &mut CheckpointReader) -> Result<Collision, CheckpointError> {
+    r.expect("collision")?;
+    let kind = r.word()?.to_string();
+    let t = if kind == "free" { Time(0.) } else { Time::load_from(r)? };
+    match kind.as_str() {
+      "free" => Ok(Collision::Free),
+      "wall" => Ok(Collision::Wall { t, prev: Particle::load_from(r)?, next: Particle::load_from(r)? }),
+      "wrap" => Ok(Collision::Wrap { t, prev: Particle::load_from(r)?, next: Particle::load_from(r)? }),
+      "exit" => Ok(Collision::Exit { t, prev: Particle::load_from(r)? }),
+      "bounce" => Ok(Collision::Bounce {
+        t,
+        prev1: Particle::load_from(r)?, prev2: Particle::load_from(r)?,
+        next1: Particle::load_from(r)?, next2: Particle::load_from(r)?
+      }),
+      "cell" => {
+        let cell = (r.usize()?, r.usize()?);
+        Ok(Collision::Cell { t, prev: Particle::load_from(r)?, next: Particle::load_from(r)?, cell })
+      },
+      _ => Err(CheckpointError::Malformed(format!("unknown collision `{}`", kind))),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{custom_float, Particle, Time, Vector};
+
+  #[test]
+  fn floats_are_read_back_exactly() {
+    let p = Particle { id: 3, x: Vector((0.1 + 0.2, -0.)), v: Vector((1. / 3., 1e-300)),
+                       r: CustomFloat::MIN_POSITIVE, m: CustomFloat::MAX,
+                       displacement: Vector((-2.5e17, custom_float::consts::PI)) };
+    let coll = Collision::Wall { t: Time(CustomFloat::INFINITY), prev: p.clone(), next: p };
+    let mut out = Vec::new();
+    write_checkpoint(&mut out, &coll).unwrap();
+    let read: Collision = read_checkpoint(&mut &out[..]).unwrap();
+    assert!(format!("{:?}", read) == format!("{:?}", coll), "{:?} did not equal {:?}", read, coll);
+  }
+
+  #[test]
+  fn other_versions_are_rejected() {
+    let text = format!("{} {}\ncollision free\n", MAGIC, CHECKPOINT_VERSION + 1);
+    match read_checkpoint::<Collision>(&mut text.as_bytes()) {
+      Err(CheckpointError::Version(v)) => assert!(v == CHECKPOINT_VERSION + 1),
+      other => panic!("unexpected result: {:?}", other),
+    }
+  }
+
+  #[test]
+  fn invalid_values_are_rejected() {
+    let restitution = format!("{} {}\n1.5 0\n", MAGIC, CHECKPOINT_VERSION);
+    let particle = format!("{} {}\nparticle 0 0 0 0 0 0 1 0 0\n", MAGIC, CHECKPOINT_VERSION);
+    let results = [
+      read_checkpoint::<Restitution>(&mut restitution.as_bytes()).map(|_| ()),
+      read_checkpoint::<Particle>(&mut particle.as_bytes()).map(|_| ()),
+    ];
+    for result in &results {
+      match *result {
+        Err(CheckpointError::Malformed(_)) => (),
+        ref other => panic!("unexpected result: {:?}", other),
+      }
+    }
+  }
+}
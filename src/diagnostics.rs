@@ -0,0 +1,198 @@
+use super::{CustomFloat, Particle, Space, SpaceTime, Vector};
+
+/// Conserved quantities of a `Space`, summed over all its particles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Totals {
+  pub energy: CustomFloat,
+  pub momentum: Vector,
+  /// Angular momentum about the centre given to `Totals::of`.
+  pub angular_momentum: CustomFloat,
+}
+
+impl Totals {
+  /// Sums the kinetic energy, momentum and angular momentum about `centre`
+  /// of the particles in `space`. If `gravity` is nonzero, the energy
+  /// includes the potential energy due to it.
+  pub fn of<S: Space>(space: &S, centre: &Vector, gravity: &Vector) -> Totals {
+    space.particles().fold(
+      Totals { energy: 0., momentum: Vector((0., 0.)), angular_momentum: 0. },
+      |totals, p: &Particle| {
+        let momentum = p.v.scale(p.m);
+        Totals {
+          energy: totals.energy + 0.5 * p.m * p.v.norm2() - p.m * (gravity * &p.x),
+          momentum: &totals.momentum + &momentum,
+          angular_momentum: totals.angular_momentum + (&p.x - centre).cross(&momentum),
+        }
+      })
+  }
+}
+
+/// The change in each of the `Totals` since the start of a simulation,
+/// relative to the initial state.
+///
+/// Since the total momentum may well be zero, changes in momentum and
+/// angular momentum are relative to the sums of their magnitudes over
+/// the particles initially.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Drift {
+  pub energy: CustomFloat,
+  pub momentum: CustomFloat,
+  pub angular_momentum: CustomFloat,
+}
+
+/// What to do when a quantity drifts further than its tolerance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnDrift {
+  Warn,
+  Panic,
+}
+
+/// Tracks the conserved quantities of a simulation over time, checking that
+/// they drift from their initial values by no more than a tolerance.
+///
+/// Only energy is checked by default. Walls exchange momentum with the
+/// particles, so momentum is only conserved under periodic boundaries, and
+/// angular momentum only without any boundaries. Inelastic collisions
+/// dissipate energy.
+#[derive(Debug, Clone)]
+pub struct ConservationMonitor {
+  centre: Vector,
+  gravity: Vector,
+  initial: Totals,
+  scale: Drift,
+  tolerance: Drift,
+  on_drift: OnDrift,
+  max_drift: Drift,
+}
+
+impl ConservationMonitor {
+  pub fn new<S: Space>(initial: &SpaceTime<S>, centre: Vector) -> ConservationMonitor {
+    let gravity = Vector((0., 0.));
+    ConservationMonitor {
+      initial: Totals::of(&initial.space, &centre, &gravity),
+      scale: ConservationMonitor::scale(&initial.space, &centre, &gravity),
+      centre,
+      gravity,
+      tolerance: Drift {
+        energy: 1e-8,
+        momentum: CustomFloat::INFINITY,
+        angular_momentum: CustomFloat::INFINITY,
+      },
+      on_drift: OnDrift::Warn,
+      max_drift: Drift { energy: 0., momentum: 0., angular_momentum: 0. },
+    }
+  }
+
+  /// Sets the largest relative drift allowed in each quantity.
+  /// An infinite tolerance disables the check for that quantity.
+  pub fn with_tolerance(self, tolerance: Drift) -> ConservationMonitor {
+    ConservationMonitor { tolerance, .. self }
+  }
+
+  pub fn with_on_drift(self, on_drift: OnDrift) -> ConservationMonitor {
+    ConservationMonitor { on_drift, .. self }
+  }
+
+  /// Includes the potential energy due to the uniform acceleration `g`,
+  /// which must be that of the simulation, in the total energy.
+  pub fn with_gravity<S: Space>(self, initial: &SpaceTime<S>, g: Vector) -> ConservationMonitor {
+    ConservationMonitor {
+      initial: Totals::of(&initial.space, &self.centre, &g),
+      scale: ConservationMonitor::scale(&initial.space, &self.centre, &g),
+      gravity: g,
+      .. self
+    }
+  }
+
+  pub fn initial(&self) -> &Totals {
+    &self.initial
+  }
+
+  /// Returns the largest drift seen in each quantity so far.
+  pub fn max_drift(&self) -> &Drift {
+    &self.max_drift
+  }
+
+  /// Computes the drift of `s` from the initial state,
+  /// warning or panicking if it exceeds the tolerance.
+  pub fn check<S: Space>(&mut self, s: &SpaceTime<S>) -> Drift {
+    let totals = Totals::of(&s.space, &self.centre, &self.gravity);
+    let drift = Drift {
+      energy: (totals.energy - self.initial.energy).abs() / self.scale.energy,
+      momentum: (&totals.momentum - &self.initial.momentum).norm() / self.scale.momentum,
+      angular_momentum:
+        (totals.angular_momentum - self.initial.angular_momentum).abs() / self.scale.angular_momentum,
+    };
+
+    self.max_drift = Drift {
+      energy: self.max_drift.energy.max(drift.energy),
+      momentum: self.max_drift.momentum.max(drift.momentum),
+      angular_momentum: self.max_drift.angular_momentum.max(drift.angular_momentum),
+    };
+
+    if drift.energy > self.tolerance.energy ||
+       drift.momentum > self.tolerance.momentum ||
+       drift.angular_momentum > self.tolerance.angular_momentum {
+      match self.on_drift {
+        OnDrift::Warn => warn!(
+          "conserved quantities drifted at t: {:?}\ndrift: {:?}\ntotals: {:?}\ninitial: {:?}",
+          s.time, drift, totals, self.initial),
+        OnDrift::Panic => panic!(
+          "conserved quantities drifted at t: {:?}\ndrift: {:?}\ntotals: {:?}\ninitial: {:?}",
+          s.time, drift, totals, self.initial),
+      }
+    }
+    drift
+  }
+
+  /// Computes the scale against which drift is measured,
+  /// which is nonzero unless every particle is at rest.
+  fn scale<S: Space>(space: &S, centre: &Vector, gravity: &Vector) -> Drift {
+    let energy = Totals::of(space, centre, gravity).energy.abs();
+    let (momentum, angular_momentum) = space.particles()
+      .fold((0., 0.), |(p_sum, l_sum), p| {
+        let momentum = p.v.scale(p.m);
+        (p_sum + momentum.norm(), l_sum + (&p.x - centre).norm() * momentum.norm())
+      });
+    let nonzero = |x: CustomFloat| if x > 0. { x } else { 1. };
+    Drift {
+      energy: nonzero(energy),
+      momentum: nonzero(momentum),
+      angular_momentum: nonzero(angular_momentum),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{Particle, SeededRng, SpaceBox, SpaceTime, Time, Vector};
+
+  #[test]
+  fn totals_of_two_particles() {
+    let p1 = Particle::new(0, Vector((1., 0.)), Vector((0., 1.)), 0.5, 2.);
+    let p2 = Particle::new(1, Vector((-1., 0.)), Vector((1., 0.)), 0.5, 1.);
+    let space = SpaceBox::new(vec![p1, p2], Vector((-5., -5.)), Vector((5., 5.)));
+    let totals = Totals::of(&space, &Vector((0., 0.)), &Vector((0., 0.)));
+    assert!(totals == Totals {
+      energy: 1.5,
+      momentum: Vector((1., 2.)),
+      angular_momentum: 2.,
+    }, "{:?}", totals);
+  }
+
+  #[test]
+  fn energy_is_conserved_in_a_box() {
+    let min = Particle::new(0, Vector((-10., -10.)), Vector((-1., -1.)), 0.5, 1.);
+    let max = Particle::new(0, Vector((10., 10.)), Vector((1., 1.)), 0.5, 2.);
+    let mut rng = SeededRng::new(123);
+    let init = SpaceTime::new(SpaceBox::new_random(&mut rng, 30, min, max).unwrap(), Time(0.));
+    let mut monitor = ConservationMonitor::new(&init, Vector((0., 0.)))
+      .with_on_drift(OnDrift::Panic);
+
+    for s in init.every(Time(0.5)).take(100) {
+      monitor.check(&s);
+    }
+    assert!(monitor.max_drift().energy < 1e-10);
+  }
+}
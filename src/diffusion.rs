@@ -0,0 +1,147 @@
+use std::collections::{HashMap, VecDeque};
+use super::{CustomFloat, Estimate, Space, SpaceTime, Time, Vector};
+use super::custom_float::consts::PI;
+use super::pressure::henderson_contact_value;
+
+/// The mean squared displacement of particles, MSD(t), averaged over
+/// multiple time origins, from which the self-diffusion coefficient is
+/// estimated.
+///
+/// States must be sampled once every `step`, such as from
+/// `SpaceTime::every`. A new time origin starts every `origin_every`
+/// samples, and displacements are tracked from it for up to `max_lag`
+/// samples. Particles are matched between samples by their ids, so that
+/// those removed by absorbing or open boundaries are left out.
+#[derive(Debug, Clone)]
+pub struct MeanSquaredDisplacement {
+  step: Time,
+  origin_every: usize,
+  max_lag: usize,
+  samples: usize,
+  // the sample at which each origin started, and each particle's
+  // displacement at that time
+  origins: VecDeque<(usize, HashMap<usize, Vector>)>,
+  // the sum and number of squared displacements at each lag
+  sums: Vec<CustomFloat>,
+  counts: Vec<usize>,
+}
+
+impl MeanSquaredDisplacement {
+  pub fn new(step: Time, origin_every: usize, max_lag: usize) -> MeanSquaredDisplacement {
+    assert!(origin_every > 0, "origins must be at least one sample apart");
+    MeanSquaredDisplacement {
+      step,
+      origin_every,
+      max_lag,
+      samples: 0,
+      origins: VecDeque::new(),
+      sums: vec![0.; max_lag + 1],
+      counts: vec![0; max_lag + 1],
+    }
+  }
+
+  pub fn sample<S: Space>(&mut self, s: &SpaceTime<S>) {
+    if self.samples.is_multiple_of(self.origin_every) {
+      let displacements = s.space.particles().map(|p| (p.id, p.displacement)).collect();
+      self.origins.push_back((self.samples, displacements));
+    }
+
+    for &(start, ref origin) in &self.origins {
+      let lag = self.samples - start;
+      for p in s.space.particles() {
+        if let Some(d0) = origin.get(&p.id) {
+          self.sums[lag] += (&p.displacement - d0).norm2();
+          self.counts[lag] += 1;
+        }
+      }
+    }
+
+    self.samples += 1;
+    let max_lag = self.max_lag;
+    let samples = self.samples;
+    while self.origins.front().is_some_and(|&(start, _)| samples - start > max_lag) {
+      self.origins.pop_front();
+    }
+  }
+
+  /// Returns the mean squared displacement at each lag sampled so far.
+  pub fn msd(&self) -> Vec<(Time, CustomFloat)> {
+    self.sums.iter().zip(self.counts.iter()).enumerate()
+      .filter(|&(_, (_, &count))| count > 0)
+      .map(|(lag, (&sum, &count))| (Time(lag as CustomFloat * self.step.0), sum / count as CustomFloat))
+      .collect()
+  }
+
+  /// Estimates the self-diffusion coefficient from the slope of the MSD,
+  /// which in 2D grows as 4Dt, fitted by least squares over lags of at
+  /// least `min_lag`. This should skip the ballistic motion at short lags.
+  ///
+  /// The error is that of the fit, which neglects the correlation between
+  /// lags, so it underestimates the true error.
+  pub fn diffusion_coefficient(&self, min_lag: Time) -> Estimate {
+    let points: Vec<(CustomFloat, CustomFloat)> = self.msd().into_iter()
+      .filter(|&(t, _)| t.0 >= min_lag.0)
+      .map(|(t, msd)| (t.0, msd))
+      .collect();
+    let n = points.len() as CustomFloat;
+    if points.len() < 2 {
+      return Estimate { value: CustomFloat::NAN, error: CustomFloat::INFINITY };
+    }
+
+    let t_mean = points.iter().map(|&(t, _)| t).sum::<CustomFloat>() / n;
+    let msd_mean = points.iter().map(|&(_, msd)| msd).sum::<CustomFloat>() / n;
+    let stt = points.iter().map(|&(t, _)| (t - t_mean) * (t - t_mean)).sum::<CustomFloat>();
+    let slope = points.iter()
+      .map(|&(t, msd)| (t - t_mean) * (msd - msd_mean))
+      .sum::<CustomFloat>() / stt;
+
+    let error = if points.len() > 2 {
+      let residuals = points.iter()
+        .map(|&(t, msd)| {
+          let r = msd - msd_mean - slope * (t - t_mean);
+          r * r
+        })
+        .sum::<CustomFloat>();
+      (residuals / ((n - 2.) * stt)).sqrt()
+    } else {
+      CustomFloat::INFINITY
+    };
+    Estimate { value: slope / 4., error: error / 4. }
+  }
+}
+
+/// The self-diffusion coefficient of hard disks of diameter `sigma` and mass
+/// `m`, at temperature `kt` and number density `n`, according to Enskog's
+/// theory: D = (kT / πm)^½ / 2nσg(σ), where g(σ) is the contact value at
+/// the given packing fraction.
+pub fn enskog_diffusion(n: CustomFloat, sigma: CustomFloat, m: CustomFloat, kt: CustomFloat,
+                        packing_fraction: CustomFloat) -> CustomFloat {
+  (kt / (PI * m)).sqrt() / (2. * n * sigma * henderson_contact_value(packing_fraction))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{Boundary, Particle, SpaceBox, SpaceTime, Time, Vector};
+
+  #[test]
+  fn free_particle_moves_ballistically_through_periodic_boundaries() {
+    let p = Particle::new(0, Vector((0., 0.)), Vector((1., 0.)), 0.5, 1.);
+    let space = SpaceBox::new(vec![p], Vector((-2., -2.)), Vector((2., 2.)))
+      .with_boundary(Boundary::Periodic);
+    let mut msd = MeanSquaredDisplacement::new(Time(1.), 2, 5);
+    for s in SpaceTime::new(space, Time(0.)).every(Time(1.)).take(20) {
+      msd.sample(&s);
+    }
+
+    // moving at unit speed, the displacement after each lag is the lag itself
+    let expected: Vec<(Time, CustomFloat)> = (0..6)
+      .map(|lag| (Time(lag as CustomFloat), (lag * lag) as CustomFloat))
+      .collect();
+    let actual = msd.msd();
+    assert!(
+      actual.len() == expected.len() &&
+      actual.iter().zip(expected.iter()).all(|(a, e)| a.0 == e.0 && (a.1 - e.1).abs() < 1e-10),
+      "{:?} did not equal {:?}", actual, expected);
+  }
+}
@@ -0,0 +1,20 @@
+// Small systems whose collisions happen at known times, shared by the
+// tests of the observers.
+
+use super::{Boundary, Particle, SpaceBox, Vector};
+
+/// A single particle which hits the right wall at t = 4, 20, 36, ...
+/// and the left wall at t = 12, 28, 44, ...
+pub fn bouncing_between_walls() -> SpaceBox {
+  let p = Particle::new(0, Vector((0., 0.)), Vector((1., 0.)), 1., 2.);
+  SpaceBox::new(vec![p], Vector((-5., -5.)), Vector((5., 5.)))
+}
+
+/// Two particles which collide head on at t = 0.5, then across the seam of
+/// the periodic boundary at t = 4.5, 8.5, ...
+pub fn head_on_across_periodic_boundaries() -> SpaceBox {
+  let p1 = Particle::new(0, Vector((-1., 0.)), Vector((1., 0.)), 0.5, 1.);
+  let p2 = Particle::new(1, Vector((1., 0.)), Vector((-1., 0.)), 0.5, 1.);
+  SpaceBox::new(vec![p1, p2], Vector((-5., -5.)), Vector((5., 5.)))
+    .with_boundary(Boundary::Periodic)
+}
@@ -2,7 +2,7 @@ use super::{CustomFloat};
 
 use std::cmp::{Ord, Ordering};
 
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct FloatOps(pub CustomFloat);
 
 impl Eq for FloatOps { }
@@ -14,9 +14,15 @@ impl FloatOps {
   }
 }
 
+impl PartialOrd for FloatOps {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
 impl Ord for FloatOps {
   fn cmp(&self, other: &Self) -> Ordering {
-    match self.partial_cmp(other) {
+    match self.0.partial_cmp(&other.0) {
       Some(ord) => ord,
       None => panic!("Unable to compare floats: {:?} and {:?}", self, other)
     }
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use super::{Collision, CustomFloat, Estimate, Histogram, Space, Time};
+use super::custom_float::consts::PI;
+use super::pressure::henderson_contact_value;
+
+/// Statistics of the free flights of particles between collisions with each
+/// other: the distributions of the time and distance travelled in each
+/// flight, and the rate at which particles collide.
+///
+/// Collisions with walls do not end a flight. The first flight of each
+/// particle is left out, since it began before `start`. Particles are
+/// identified by their ids, which must be unique, and must not be under
+/// gravity.
+#[derive(Debug, Clone)]
+pub struct FreePaths {
+  start: Time,
+  flights: HashMap<usize, Flight>,
+  times: Histogram,
+  paths: Histogram,
+  // sums of the times and paths of the flights, and of their squares
+  time_sums: (CustomFloat, CustomFloat),
+  path_sums: (CustomFloat, CustomFloat),
+  bounces: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Flight {
+  // when the flight began, or None for the first flight
+  start: Option<Time>,
+  // when the particle last changed direction, and its speed since
+  last: Time,
+  speed: CustomFloat,
+  // the distance travelled up until `last`
+  path: CustomFloat,
+}
+
+impl FreePaths {
+  /// Bins free flight times over `[0, max_time)`, and free paths over
+  /// `[0, max_path)`, into `bins` bins each, from `space` as it is at `start`.
+  pub fn new<S: Space>(space: &S, start: Time, max_time: CustomFloat, max_path: CustomFloat,
+                       bins: usize) -> FreePaths {
+    FreePaths {
+      start,
+      flights: space.particles()
+        .map(|p| (p.id, Flight { start: None, last: start, speed: p.v.norm(), path: 0. }))
+        .collect(),
+      times: Histogram::new(0., max_time, bins),
+      paths: Histogram::new(0., max_path, bins),
+      time_sums: (0., 0.),
+      path_sums: (0., 0.),
+      bounces: 0,
+    }
+  }
+
+  pub fn times(&self) -> &Histogram {
+    &self.times
+  }
+
+  pub fn paths(&self) -> &Histogram {
+    &self.paths
+  }
+
+  /// Returns the number of collisions between particles recorded so far.
+  pub fn bounces(&self) -> usize {
+    self.bounces
+  }
+
+  /// Follows the particle with the given id up to `time`, when its velocity
+  /// changes to one of the given speed. If `bounce`, this ends its flight.
+  fn turn(&mut self, id: usize, time: Time, speed: CustomFloat, bounce: bool) {
+    let ended = match self.flights.get_mut(&id) {
+      Some(flight) => {
+        flight.path += flight.speed * (time.0 - flight.last.0);
+        flight.last = time;
+        flight.speed = speed;
+        if bounce {
+          let ended = flight.start.map(|start| (time.0 - start.0, flight.path));
+          flight.start = Some(time);
+          flight.path = 0.;
+          ended
+        } else {
+          None
+        }
+      },
+      None => None,
+    };
+
+    if let Some((t, path)) = ended {
+      self.times.add(t);
+      self.paths.add(path);
+      self.time_sums = (self.time_sums.0 + t, self.time_sums.1 + t * t);
+      self.path_sums = (self.path_sums.0 + path, self.path_sums.1 + path * path);
+    }
+  }
+
+  /// Records a collision occurring at the absolute time `time`.
+  /// Collisions before `start` are ignored.
+  pub fn record(&mut self, time: Time, collision: &Collision) {
+    if time.0 < self.start.0 { return; }
+
+    match *collision {
+      Collision::Free | Collision::Cell { .. } | Collision::Wrap { .. } => (),
+      Collision::Wall { ref next, .. } => self.turn(next.id, time, next.v.norm(), false),
+      Collision::Exit { ref prev, .. } => { self.flights.remove(&prev.id); },
+      Collision::Bounce { ref next1, ref next2, .. } => {
+        self.bounces += 1;
+        self.turn(next1.id, time, next1.v.norm(), true);
+        self.turn(next2.id, time, next2.v.norm(), true);
+      },
+    }
+  }
+
+  /// Reports the collision frequency per particle, which assumes that none
+  /// have been removed, and the mean free time and path.
+  pub fn report(&self, now: Time) -> Vec<(String, Estimate)> {
+    let exposure = self.flights.len() as CustomFloat * (now.0 - self.start.0);
+    // each bounce is a collision for both particles
+    let collisions = 2. * self.bounces as CustomFloat;
+    let frequency = Estimate { value: collisions / exposure, error: collisions.sqrt() / exposure };
+
+    let mean = |sums: (CustomFloat, CustomFloat)| {
+      let n = self.times.total() as CustomFloat;
+      let mean = sums.0 / n;
+      let error = if n > 1. {
+        ((sums.1 / n - mean * mean) * n / (n - 1.) / n).sqrt()
+      } else {
+        CustomFloat::INFINITY
+      };
+      Estimate { value: mean, error }
+    };
+
+    vec![
+      ("collision frequency".to_string(), frequency),
+      ("mean free time".to_string(), mean(self.time_sums)),
+      ("mean free path".to_string(), mean(self.path_sums)),
+    ]
+  }
+}
+
+/// The frequency at which each hard disk of diameter `sigma` and mass `m`
+/// collides, at temperature `kt` and number density `n`, according to
+/// Enskog's theory: ω = 2nσg(σ)(πkT / m)^½, where g(σ) is the contact value
+/// at the given packing fraction.
+pub fn enskog_collision_frequency(n: CustomFloat, sigma: CustomFloat, m: CustomFloat,
+                                  kt: CustomFloat, packing_fraction: CustomFloat) -> CustomFloat {
+  2. * n * sigma * henderson_contact_value(packing_fraction) * (PI * kt / m).sqrt()
+}
+
+/// The mean free path of hard disks, according to Enskog's theory: the mean
+/// speed, (πkT / 2m)^½, over the collision frequency.
+pub fn enskog_free_path(n: CustomFloat, sigma: CustomFloat, m: CustomFloat, kt: CustomFloat,
+                        packing_fraction: CustomFloat) -> CustomFloat {
+  (PI * kt / (2. * m)).sqrt() / enskog_collision_frequency(n, sigma, m, kt, packing_fraction)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{Simulation, SpaceTime, Time};
+  use super::super::fixtures::head_on_across_periodic_boundaries;
+
+  #[test]
+  fn flights_end_at_head_on_collisions_across_periodic_boundaries() {
+    let space = head_on_across_periodic_boundaries();
+    let mut free_paths = FreePaths::new(&space, Time(0.), 10., 10., 10);
+    Simulation::new(SpaceTime::new(space, Time(0.)), Time(1.))
+      .with_observer(&mut free_paths)
+      .run(20);
+
+    let report = free_paths.report(Time(20.));
+    // 5 collisions each by t = 20, the first of which starts the first flight
+    let expected = vec![0.25, 4., 4.];
+    assert!(
+      report.iter().zip(expected.iter()).all(|(&(_, e), x)| (e.value - x).abs() < 1e-12),
+      "{:?} did not equal {:?}", report, expected);
+  }
+
+  #[test]
+  fn collisions_before_the_start_are_ignored() {
+    let space = head_on_across_periodic_boundaries();
+    let mut free_paths = FreePaths::new(&space, Time(10.), 10., 10., 10);
+    Simulation::new(SpaceTime::new(space, Time(0.)), Time(1.))
+      .with_observer(&mut free_paths)
+      .run(20);
+
+    // only the collisions at t = 12.5 and 16.5, the first starting a flight
+    assert!(free_paths.bounces() == 2, "{:?} did not equal {:?}", free_paths.bounces(), 2);
+    let report = free_paths.report(Time(20.));
+    assert!(report[1].1.value == 4., "{:?} did not equal {:?}", report[1].1.value, 4.);
+  }
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use super::{palette, Frame};
+
+/// Writes `Frame`s as an animated GIF, which loops forever, showing each
+/// frame for `delay` hundredths of a second.
+///
+/// The GIF is only complete once `finish` has been called.
+pub struct GifWriter<W: Write> {
+  out: W,
+  delay: u16,
+  // the width and height of the frames, once the first has been written
+  size: Option<(usize, usize)>,
+  seed: Option<usize>,
+}
+
+impl<W: Write> GifWriter<W> {
+  pub fn new(out: W, delay: u16) -> GifWriter<W> {
+    GifWriter { out, delay, size: None, seed: None }
+  }
+
+  /// Records the seed of the random initial state in a comment, before the
+  /// first frame.
+  pub fn with_seed(self, seed: usize) -> GifWriter<W> {
+    GifWriter { seed: Some(seed), .. self }
+  }
+
+  /// # Panics
+  /// - if the frame is not the same size as the first
+  /// - if the frame is over 65535 pixels wide or high
+  pub fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+    let size = (frame.width(), frame.height());
+    match self.size {
+      Some(first) => assert!(size == first, "frames must all be {:?}, not {:?}", first, size),
+      None => {
+        self.write_header(size)?;
+        self.size = Some(size);
+      },
+    }
+
+    // graphic control extension, with the delay
+    self.out.write_all(&[0x21, 0xf9, 4, 0])?;
+    self.out.write_all(&self.delay.to_le_bytes())?;
+    self.out.write_all(&[0, 0])?;
+
+    // image descriptor, covering the whole screen
+    self.out.write_all(&[0x2c, 0, 0, 0, 0])?;
+    self.out.write_all(&(size.0 as u16).to_le_bytes())?;
+    self.out.write_all(&(size.1 as u16).to_le_bytes())?;
+    self.out.write_all(&[0, 8])?;
+    for block in lzw_encode(frame.pixels()).chunks(255) {
+      self.out.write_all(&[block.len() as u8])?;
+      self.out.write_all(block)?;
+    }
+    self.out.write_all(&[0])
+  }
+
+  /// Ends the GIF, returning what it was written to.
+  pub fn finish(mut self) -> io::Result<W> {
+    self.out.write_all(&[0x3b])?;
+    Ok(self.out)
+  }
+
+  fn write_header(&mut self, (width, height): (usize, usize)) -> io::Result<()> {
+    assert!(width <= 0xffff && height <= 0xffff, "frames are too large for a GIF: {}x{}", width, height);
+    self.out.write_all(b"GIF89a")?;
+    self.out.write_all(&(width as u16).to_le_bytes())?;
+    self.out.write_all(&(height as u16).to_le_bytes())?;
+    // a global colour table of 256 colours, with the first as background
+    self.out.write_all(&[0xf7, 0, 0])?;
+    for colour in palette() {
+      self.out.write_all(&colour)?;
+    }
+    // loop forever
+    self.out.write_all(&[0x21, 0xff, 11])?;
+    self.out.write_all(b"NETSCAPE2.0")?;
+    self.out.write_all(&[3, 1, 0, 0, 0])?;
+    if let Some(seed) = self.seed {
+      let comment = format!("seed {}", seed);
+      self.out.write_all(&[0x21, 0xfe, comment.len() as u8])?;
+      self.out.write_all(comment.as_bytes())?;
+      self.out.write_all(&[0])?;
+    }
+    Ok(())
+  }
+}
+
+const CLEAR: u16 = 256;
+const END: u16 = 257;
+const MAX_CODES: u16 = 4096;
+
+/// Compresses 8 bit pixels with the variable width LZW coding used by GIF.
+fn lzw_encode(pixels: &[u8]) -> Vec<u8> {
+  let mut bits = BitWriter { bytes: Vec::new(), acc: 0, len: 0 };
+  let mut codes: HashMap<(u16, u8), u16> = HashMap::new();
+  let mut width = 9;
+  let mut next = END + 1;
+  bits.write(CLEAR, width);
+
+  let mut prefix = match pixels.first() {
+    Some(&first) => first as u16,
+    None => {
+      bits.write(END, width);
+      return bits.finish();
+    },
+  };
+  for &pixel in &pixels[1..] {
+    if let Some(&code) = codes.get(&(prefix, pixel)) {
+      prefix = code;
+      continue;
+    }
+
+    bits.write(prefix, width);
+    if next < MAX_CODES {
+      codes.insert((prefix, pixel), next);
+      next += 1;
+      // the decoder adds each code a step later, so widens a step later
+      if next > 1 << width && width < 12 {
+        width += 1;
+      }
+    } else {
+      bits.write(CLEAR, width);
+      codes.clear();
+      width = 9;
+      next = END + 1;
+    }
+    prefix = pixel as u16;
+  }
+  bits.write(prefix, width);
+  bits.write(END, width);
+  bits.finish()
+}
+
+/// Packs codes into bytes, least significant bit first.
+struct BitWriter {
+  bytes: Vec<u8>,
+  acc: u32,
+  len: u32,
+}
+
+impl BitWriter {
+  fn write(&mut self, code: u16, width: u32) {
+    self.acc |= (code as u32) << self.len;
+    self.len += width;
+    while self.len >= 8 {
+      self.bytes.push(self.acc as u8);
+      self.acc >>= 8;
+      self.len -= 8;
+    }
+  }
+
+  fn finish(mut self) -> Vec<u8> {
+    if self.len > 0 {
+      self.bytes.push(self.acc as u8);
+    }
+    self.bytes
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // a straightforward decoder, to check that the encoder round trips
+  fn lzw_decode(bytes: &[u8]) -> Vec<u8> {
+    let (mut pos, mut width) = (0, 9);
+    let mut read = |width: usize| {
+      let code = (0..width).fold(0, |code, i| {
+        let bit = (bytes[(pos + i) / 8] >> ((pos + i) % 8)) & 1;
+        code | (bit as usize) << i
+      });
+      pos += width;
+      code
+    };
+
+    let mut table: Vec<Vec<u8>> = Vec::new();
+    let mut out = Vec::new();
+    let mut prev: Option<Vec<u8>> = None;
+    loop {
+      let code = read(width);
+      if code == CLEAR as usize {
+        table = (0..256).map(|b| vec![b as u8]).chain(vec![vec![], vec![]]).collect();
+        width = 9;
+        prev = None;
+        continue;
+      } else if code == END as usize {
+        return out;
+      }
+
+      let entry = match (table.get(code), prev.as_ref()) {
+        (Some(entry), _) => entry.clone(),
+        (None, Some(prev)) => prev.iter().cloned().chain(Some(prev[0])).collect(),
+        (None, None) => panic!("code {} is not yet defined", code),
+      };
+      out.extend_from_slice(&entry);
+      if let Some(prev) = prev {
+        if table.len() < MAX_CODES as usize {
+          table.push(prev.iter().cloned().chain(Some(entry[0])).collect());
+        }
+      }
+      if table.len() == 1 << width && width < 12 {
+        width += 1;
+      }
+      prev = Some(entry);
+    }
+  }
+
+  #[test]
+  fn lzw_round_trips_through_every_code_width() {
+    // enough varied pixels to fill the table, and start again
+    let pixels: Vec<u8> = (0..40000u32).map(|i| ((i * i / 7 + i / 300) % 256) as u8).collect();
+    let decoded = lzw_decode(&lzw_encode(&pixels));
+    assert!(decoded == pixels, "decoded {} pixels, not {}", decoded.len(), pixels.len());
+    assert!(lzw_decode(&lzw_encode(&[])).is_empty());
+  }
+}
@@ -0,0 +1,137 @@
+use super::CustomFloat;
+
+/// Counts of values falling into equal-width bins over `[min, max)`,
+/// along with those falling below or above the range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+  min: CustomFloat,
+  max: CustomFloat,
+  counts: Vec<usize>,
+  below: usize,
+  above: usize,
+}
+
+/// How well a `Histogram` agrees with an expected distribution.
+///
+/// Values sampled from a simulation are usually correlated, so `chi2` should
+/// be compared against `dof` as a measure of fit, rather than used for a
+/// significance test.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fit {
+  /// Pearson's chi-squared statistic.
+  pub chi2: CustomFloat,
+  /// The number of degrees of freedom in `chi2`.
+  pub dof: usize,
+  /// The Kullback–Leibler divergence of the observed distribution from
+  /// the expected one, in nats.
+  pub kl_divergence: CustomFloat,
+}
+
+impl Histogram {
+  pub fn new(min: CustomFloat, max: CustomFloat, bins: usize) -> Histogram {
+    assert!(min < max, "histogram range must be nonempty: [{}, {})", min, max);
+    assert!(bins > 0, "histogram must have at least one bin");
+    Histogram { min, max, counts: vec![0; bins], below: 0, above: 0 }
+  }
+
+  pub fn add(&mut self, x: CustomFloat) {
+    if x < self.min {
+      self.below += 1;
+    } else if x >= self.max {
+      self.above += 1;
+    } else {
+      let bin = ((x - self.min) / self.width()).floor() as usize;
+      // rounding may put x just below max into the bin after the last
+      let last = self.counts.len() - 1;
+      self.counts[bin.min(last)] += 1;
+    }
+  }
+
+  pub fn width(&self) -> CustomFloat {
+    (self.max - self.min) / self.counts.len() as CustomFloat
+  }
+
+  /// Returns the lower and upper edges of the `i`th bin.
+  pub fn edges(&self, i: usize) -> (CustomFloat, CustomFloat) {
+    let w = self.width();
+    (self.min + i as CustomFloat * w, self.min + (i + 1) as CustomFloat * w)
+  }
+
+  pub fn counts(&self) -> &[usize] {
+    &self.counts
+  }
+
+  /// Returns the number of values below and above the range, respectively.
+  pub fn outside(&self) -> (usize, usize) {
+    (self.below, self.above)
+  }
+
+  /// Returns the number of values added, including those outside the range.
+  pub fn total(&self) -> usize {
+    self.counts.iter().sum::<usize>() + self.below + self.above
+  }
+
+  /// Returns the probability density estimated from each bin.
+  pub fn density(&self) -> Vec<CustomFloat> {
+    let norm = self.total() as CustomFloat * self.width();
+    self.counts.iter().map(|&c| if norm > 0. { c as CustomFloat / norm } else { 0. }).collect()
+  }
+
+  /// Compares the histogram against a distribution, given as a function
+  /// returning the probability of a value lying between two bounds.
+  /// The values below and above the range are compared as two extra bins.
+  pub fn compare<F>(&self, probability: F) -> Fit
+  where F: Fn(CustomFloat, CustomFloat) -> CustomFloat {
+    let total = self.total() as CustomFloat;
+    let mut categories = vec![
+      (self.below, probability(-CustomFloat::INFINITY, self.min)),
+      (self.above, probability(self.max, CustomFloat::INFINITY)),
+    ];
+    categories.extend(self.counts.iter().enumerate().map(|(i, &c)| {
+      let (lo, hi) = self.edges(i);
+      (c, probability(lo, hi))
+    }));
+
+    let mut fit = Fit { chi2: 0., dof: 0, kl_divergence: 0. };
+    for (observed, p) in categories {
+      let expected = p * total;
+      let observed = observed as CustomFloat;
+      if expected > 0. {
+        fit.chi2 += (observed - expected) * (observed - expected) / expected;
+        fit.dof += 1;
+      } else if observed > 0. {
+        fit.chi2 = CustomFloat::INFINITY;
+      }
+      if observed > 0. {
+        let q = observed / total;
+        fit.kl_divergence += q * (q / p).ln();
+      }
+    }
+    fit.dof = fit.dof.saturating_sub(1);
+    fit
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn uniform_values_fit_uniform_distribution() {
+    let uniform = |lo: CustomFloat, hi: CustomFloat| (hi.min(1.) - lo.max(0.)).max(0.);
+    let mut histogram = Histogram::new(0., 1., 4);
+    for i in 0..100 {
+      histogram.add(i as CustomFloat / 100.);
+    }
+    assert!(histogram.counts() == [25, 25, 25, 25], "{:?}", histogram);
+
+    let fit = histogram.compare(uniform);
+    assert!(fit == Fit { chi2: 0., dof: 3, kl_divergence: 0. }, "{:?}", fit);
+
+    // a value outside the range was not expected
+    histogram.add(1.);
+    assert!(histogram.outside() == (0, 1), "{:?}", histogram);
+    let fit = histogram.compare(uniform);
+    assert!(fit.chi2 == CustomFloat::INFINITY, "{:?}", fit);
+  }
+}
@@ -10,25 +10,68 @@ mod custom_float {
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct Time(pub CustomFloat);
 
-pub use bounds::Bounds;
+pub use bounds::{Boundary, Bounds, Side};
 pub use float::FloatOps;
 pub use vector::Vector;
 pub use particle::{Particle};
-pub use bounded_rand::BoundedRand;
+pub use restitution::Restitution;
+pub use bounded_rand::{BoundedRand, SeededRng};
+pub use packing::{InitError, Lattice, Velocities};
 pub use cartesian_iter::{Combination2, Combination2Iter};
+pub use calendar::{Calendar, Event};
+pub use cell_list::CellList;
 pub use space::{Collision, Space};
 pub use space_vec::SpaceVec;
 pub use space_box::SpaceBox;
-pub use spacetime::SpaceTime;
+pub use spacetime::{CollisionRecord, SpaceTime};
+pub use checkpoint::{CheckpointError, CHECKPOINT_VERSION};
+pub use diagnostics::{ConservationMonitor, Drift, OnDrift, Totals};
+pub use observable::{packing_fraction, temperature, Estimate};
+pub use pressure::{henderson_contact_value, henderson_pressure, ideal_gas_pressure, VirialPressure,
+  WallPressure};
+pub use histogram::{Fit, Histogram};
+pub use velocity::VelocityDistribution;
+pub use radial::RadialDistribution;
+pub use diffusion::{enskog_diffusion, MeanSquaredDisplacement};
+pub use autocorrelation::VelocityAutocorrelation;
+pub use free_path::{enskog_collision_frequency, enskog_free_path, FreePaths};
+pub use observer::{Observer, Simulation};
+pub use xyz::XyzWriter;
+pub use render::{palette, Colouring, Frame, Renderer};
+pub use gif::GifWriter;
+pub use svg::SvgWriter;
+pub use scenario::{Amount, Format, Init, Length, Observation, Scenario, ScenarioError, Species};
 
 mod bounds;
 mod float;
 mod vector;
 mod particle;
+mod restitution;
 mod bounded_rand;
+mod packing;
 mod cartesian_iter;
+mod calendar;
+mod cell_list;
 mod space;
 mod space_box;
 mod space_vec;
 mod spacetime;
+mod checkpoint;
+mod diagnostics;
+mod observable;
+mod pressure;
+mod histogram;
+mod velocity;
+mod radial;
+mod diffusion;
+mod autocorrelation;
+mod free_path;
+mod observer;
+mod xyz;
+mod render;
+mod gif;
+mod svg;
+mod scenario;
+#[cfg(test)]
+mod fixtures;
 
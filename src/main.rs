@@ -2,65 +2,453 @@ extern crate fern;
 #[macro_use]
 extern crate log;
 extern crate particles;
-extern crate rand;
 
-use std::usize;
-use std::string::String;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::process;
+use std::str::FromStr;
 use particles::*;
-use rand::{StdRng};
 
-fn init_logger() {
-  fern::init_global_logger(
-    fern::OutputConfig::stderr(),
-    log::LogLevelFilter::Trace
-  ).unwrap();
+const USAGE: &str = "\
+Usage: particles [OPTIONS]
+       particles --scenario FILE [-v | -q]
+
+Simulates hard disks in a box, from a random initial state,
+and writes the states sampled after every step.
+
+Options:
+  -s, --scenario FILE     read the whole simulation from a scenario file,
+                          rather than from the options below
+  -n, --count N           number of particles [default: 6]
+      --fraction F        fill the box until the particles cover this fraction
+                          of it, rather than with a number of them
+      --box WIDTH,HEIGHT  size of the box, centred on the origin [default: 12,12]
+      --radius MIN,MAX    range of particle radii [default: 0.3,1]
+      --mass MIN,MAX      range of particle masses [default: 1,1]
+      --velocity MIN,MAX  range of each component of particle velocities [default: -1,1]
+      --step T            time between samples [default: 0.1]
+      --frames N          number of states to sample [default: 100]
+      --until T           sample states up to time T, rather than a number of them
+      --seed N            seed of the random initial state, which is logged and
+                          recorded in the output [default: from the OS]
+  -f, --format FORMAT     gif, svg, xyz, png or ppm [default: gif]
+  -o, --output PATH       file to write, or - for stdout [default: -]
+                          png and ppm write a file per frame, PATH-0000.png and so on
+  -v, --verbose           log debugging information, or with -vv, everything
+  -q, --quiet             only log errors
+  -h, --help              show this message
+";
+
+/// Width in pixels of rendered frames.
+const FRAME_WIDTH: usize = 400;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Options {
+  count: usize,
+  fraction: Option<CustomFloat>,
+  size: (CustomFloat, CustomFloat),
+  radius: (CustomFloat, CustomFloat),
+  mass: (CustomFloat, CustomFloat),
+  velocity: (CustomFloat, CustomFloat),
+  step: Time,
+  length: Length,
+  seed: Option<usize>,
+  format: Format,
+  output: String,
+  scenario: Option<String>,
+  log_level: log::LogLevelFilter,
+  help: bool,
 }
 
-fn main() {
-  init_logger();
+impl Default for Options {
+  fn default() -> Options {
+    Options {
+      count: 6,
+      fraction: None,
+      size: (12., 12.),
+      radius: (0.3, 1.),
+      mass: (1., 1.),
+      velocity: (-1., 1.),
+      step: Time(0.1),
+      length: Length::Frames(100),
+      seed: None,
+      format: Format::Gif,
+      output: "-".to_string(),
+      scenario: None,
+      log_level: log::LogLevelFilter::Info,
+      help: false,
+    }
+  }
+}
+
+impl Options {
+  /// Parses the arguments given after the name of the program.
+  fn parse<I: Iterator<Item=String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut length = None;
+    // the first option which describes the simulation, which a scenario replaces
+    let mut simulation_option = None;
+    let mut count_option = None;
+    while let Some(arg) = args.next() {
+      let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+      match arg.as_str() {
+        "-s" | "--scenario" | "-v" | "--verbose" | "-vv" | "-q" | "--quiet" | "-h" | "--help" => (),
+        _ => if simulation_option.is_none() { simulation_option = Some(arg.clone()); },
+      }
+      match arg.as_str() {
+        "-s" | "--scenario" => options.scenario = Some(value()?),
+        "-n" | "--count" => {
+          options.count = parse(&value()?)?;
+          count_option = Some(arg.clone());
+        },
+        "--fraction" => options.fraction = Some(parse(&value()?)?),
+        "--box" => options.size = parse_pair(&value()?)?,
+        "--radius" => options.radius = parse_range(&value()?)?,
+        "--mass" => options.mass = parse_range(&value()?)?,
+        "--velocity" => options.velocity = parse_range(&value()?)?,
+        "--step" => options.step = Time(parse(&value()?)?),
+        "--frames" => length = Some(Length::Frames(parse(&value()?)?)),
+        "--until" => length = Some(Length::Until(Time(parse(&value()?)?))),
+        "--seed" => options.seed = Some(parse(&value()?)?),
+        "-f" | "--format" => options.format = value()?.parse()?,
+        "-o" | "--output" => options.output = value()?,
+        "-v" | "--verbose" => options.log_level = match options.log_level {
+          log::LogLevelFilter::Debug | log::LogLevelFilter::Trace => log::LogLevelFilter::Trace,
+          _ => log::LogLevelFilter::Debug,
+        },
+        "-vv" => options.log_level = log::LogLevelFilter::Trace,
+        "-q" | "--quiet" => options.log_level = log::LogLevelFilter::Error,
+        "-h" | "--help" => options.help = true,
+        _ => return Err(format!("unknown argument `{}`", arg)),
+      }
+    }
+
+    if let Some(length) = length { options.length = length; }
+    if let (Some(_), Some(arg)) = (options.scenario.as_ref(), simulation_option) {
+      return Err(format!("{} cannot be combined with a scenario", arg));
+    }
+    if let (Some(_), Some(arg)) = (options.fraction, count_option) {
+      return Err(format!("{} cannot be combined with --fraction", arg));
+    }
+    // NaN is not positive either
+    let positive = |x: CustomFloat| x > 0.;
+    if !positive(options.step.0) {
+      return Err("the step must be positive".to_string());
+    }
+    if options.fraction.is_some_and(|f| !(positive(f) && f < 1.)) {
+      return Err("the fraction must be within (0, 1)".to_string());
+    }
+    if !positive(options.radius.0) || !positive(options.mass.0) {
+      return Err("radii and masses must be positive".to_string());
+    }
+    if options.size.0 <= 2. * options.radius.1 || options.size.1 <= 2. * options.radius.1 {
+      return Err("the box must be wider than the largest particle".to_string());
+    }
+    if (options.format == Format::Png || options.format == Format::Ppm) && options.output == "-" {
+      return Err("png and ppm frames must be written to files with --output".to_string());
+    }
+    Ok(options)
+  }
+
+  /// Returns the scenario described by the options: a single species in a
+  /// box centred on the origin, whose conservation and wall pressure are
+  /// observed.
+  fn scenario(&self) -> Scenario {
+    let corner = Vector((self.size.0 / 2., self.size.1 / 2.));
+    let mut scenario = Scenario::new(corner.scale(-1.), corner);
+    scenario.step = self.step;
+    scenario.length = self.length;
+    scenario.seed = self.seed;
+    scenario.species.push(Species {
+      amount: match self.fraction {
+        Some(fraction) => Amount::Fraction(fraction),
+        None => Amount::Count(self.count, self.count),
+      },
+      radius: self.radius,
+      mass: self.mass,
+      velocity: Velocities::Uniform(self.velocity.0, self.velocity.1),
+    });
+    scenario.observers = vec![Observation::Conservation, Observation::WallPressure { block: Time(1.) }];
+    scenario.outputs.push((self.format, self.output.clone()));
+    scenario
+  }
+}
+
+fn parse<T: FromStr>(s: &str) -> Result<T, String> {
+  s.parse().map_err(|_| format!("invalid number `{}`", s))
+}
+
+fn parse_pair(s: &str) -> Result<(CustomFloat, CustomFloat), String> {
+  let mut parts = s.split(',');
+  match (parts.next(), parts.next(), parts.next()) {
+    (Some(a), Some(b), None) => Ok((parse(a)?, parse(b)?)),
+    _ => Err(format!("expected two numbers separated by a comma, not `{}`", s)),
+  }
+}
+
+fn parse_range(s: &str) -> Result<(CustomFloat, CustomFloat), String> {
+  let (min, max) = parse_pair(s)?;
+  if min <= max { Ok((min, max)) } else { Err(format!("the range `{}` is empty", s)) }
+}
+
+/// Where the sampled states are written.
+enum Output {
+  Gif(Renderer, GifWriter<Box<dyn Write>>),
+  // an animated SVG needs every state before it can be written
+  Svg(SvgWriter, Vec<SpaceTime<SpaceBox>>, Box<dyn Write>),
+  Xyz(XyzWriter<Box<dyn Write>>),
+  Images(Renderer, Format, String, usize),
+}
+
+impl Output {
+  /// Opens `path` for states sampled a `step` apart, drawn from `seed`.
+  fn open(format: Format, path: &str, step: Time, seed: usize) -> io::Result<Output> {
+    let out = || -> io::Result<Box<dyn Write>> {
+      if path == "-" {
+        Ok(Box::new(io::stdout()))
+      } else {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+      }
+    };
+    Ok(match format {
+      Format::Gif => Output::Gif(
+        Renderer::new(FRAME_WIDTH),
+        GifWriter::new(out()?, (100. * step.0).round() as u16).with_seed(seed)),
+      Format::Svg => Output::Svg(SvgWriter::new(FRAME_WIDTH as CustomFloat).with_seed(seed), Vec::new(), out()?),
+      Format::Xyz => Output::Xyz(XyzWriter::new(out()?).with_seed(seed)),
+      Format::Png | Format::Ppm if path == "-" => return Err(io::Error::new(
+        io::ErrorKind::InvalidInput, "png and ppm frames must be written to files")),
+      Format::Png | Format::Ppm => Output::Images(
+        Renderer::new(FRAME_WIDTH).with_seed(seed), format, path.to_string(), 0),
+    })
+  }
+
+  fn write(&mut self, s: &SpaceTime<SpaceBox>) -> io::Result<()> {
+    match *self {
+      Output::Gif(ref renderer, ref mut gif) => gif.write_frame(&renderer.render(s)),
+      Output::Svg(_, ref mut states, _) => {
+        states.push(s.clone());
+        Ok(())
+      },
+      Output::Xyz(ref mut xyz) => xyz.write_frame(s),
+      Output::Images(ref renderer, format, ref path, ref mut frames) => {
+        let frame = renderer.render(s);
+        let mut file = BufWriter::new(File::create(format!(
+          "{}-{:04}.{}", path, frames, if format == Format::Png { "png" } else { "ppm" }))?);
+        *frames += 1;
+        if format == Format::Png { frame.write_png(&mut file) } else { frame.write_ppm(&mut file) }
+      },
+    }
+  }
 
-  const NUM_PARTICLES: usize = 6;
-  const STEP: Time = Time(0.1);
+  fn finish(self, step: Time) -> io::Result<()> {
+    match self {
+      Output::Gif(_, gif) => gif.finish()?.flush(),
+      Output::Svg(svg, states, mut out) => {
+        svg.animation(&mut out, &states, step.0)?;
+        out.flush()
+      },
+      Output::Xyz(xyz) => xyz.into_inner().flush(),
+      Output::Images(..) => Ok(()),
+    }
+  }
+}
+
+/// An observer of a simulation, kept by type so that its results can be
+/// logged once the simulation has ended.
+enum Measurement {
+  Conservation(ConservationMonitor),
+  Velocity(VelocityDistribution),
+  Radial(RadialDistribution),
+  Msd(MeanSquaredDisplacement, Time),
+  // the results of these are all in the report of the simulation
+  WallPressure(WallPressure),
+  VirialPressure(VirialPressure),
+  Vacf(VelocityAutocorrelation),
+  FreePaths(FreePaths),
+}
+
+impl Measurement {
+  fn new(observation: &Observation, init: &SpaceTime<SpaceBox>, step: Time) -> Measurement {
+    let area = init.space.bounds().area();
+    match *observation {
+      Observation::Conservation => Measurement::Conservation(
+        ConservationMonitor::new(init, init.space.bounds().centre())
+          .with_gravity(init, *init.space.space_vec().gravity())),
+      Observation::WallPressure { block } => Measurement::WallPressure(
+        WallPressure::new(&init.space, init.time, block)),
+      Observation::VirialPressure { block } => Measurement::VirialPressure(
+        VirialPressure::new(&init.space, area, init.time, block)),
+      Observation::Velocity { max_speed, bins } => Measurement::Velocity(
+        VelocityDistribution::new(max_speed, bins)),
+      Observation::Radial { r_max, bins } => Measurement::Radial(
+        RadialDistribution::new(init.space.bounds(), r_max, bins)),
+      Observation::Msd { origin_every, max_lag } => Measurement::Msd(
+        MeanSquaredDisplacement::new(step, origin_every, max_lag), Time(step.0 * max_lag as CustomFloat / 2.)),
+      Observation::Vacf { lag_step, max_lag, origin_every } => Measurement::Vacf(
+        VelocityAutocorrelation::new(&init.space, init.time, lag_step, max_lag, origin_every)),
+      Observation::FreePaths { max_time, max_path, bins } => Measurement::FreePaths(
+        FreePaths::new(&init.space, init.time, max_time, max_path, bins)),
+    }
+  }
+
+  fn observer(&mut self) -> &mut dyn Observer<SpaceBox> {
+    match *self {
+      Measurement::Conservation(ref mut monitor) => monitor,
+      Measurement::Velocity(ref mut velocities) => velocities,
+      Measurement::Radial(ref mut radial) => radial,
+      Measurement::Msd(ref mut msd, _) => msd,
+      Measurement::WallPressure(ref mut pressure) => pressure,
+      Measurement::VirialPressure(ref mut pressure) => pressure,
+      Measurement::Vacf(ref mut vacf) => vacf,
+      Measurement::FreePaths(ref mut free_paths) => free_paths,
+    }
+  }
+
+  fn log(&self) {
+    match *self {
+      Measurement::Conservation(ref monitor) => info!("maximum drift: {:?}", monitor.max_drift()),
+      Measurement::Velocity(ref velocities) => info!("temperature from velocities: {}, speed fit: {:?}",
+        velocities.temperature(), velocities.speed_fit()),
+      Measurement::Radial(ref radial) => info!("radial distribution: {:?}", radial.g()),
+      // fitted over the second half of the lags, once motion is diffusive
+      Measurement::Msd(ref msd, min_lag) => {
+        let d = msd.diffusion_coefficient(min_lag);
+        info!("diffusion coefficient from displacements: {} +/- {}", d.value, d.error);
+      },
+      Measurement::WallPressure(_) |
+      Measurement::VirialPressure(_) |
+      Measurement::Vacf(_) |
+      Measurement::FreePaths(_) => (),
+    }
+  }
+}
+
+fn init_logger(level: log::LogLevelFilter) {
+  fern::init_global_logger(fern::OutputConfig::stderr(), level).unwrap();
+}
+
+fn fail(message: &str) -> ! {
+  eprintln!("error: {}", message);
+  process::exit(1);
+}
+
+fn main() {
+  let options = match Options::parse(env::args().skip(1)) {
+    Ok(options) => options,
+    Err(message) => {
+      eprint!("error: {}\n\n{}", message, USAGE);
+      process::exit(2);
+    }
+  };
+  if options.help {
+    print!("{}", USAGE);
+    return;
+  }
+  init_logger(options.log_level);
 
-  let max_particle = Particle {
-    id: 0,
-    x: Vector((5., 5.)),
-    v: Vector((1., 1.)),
-    m: 1.,
-    r: 0.3
+  let scenario = match options.scenario {
+    Some(ref path) => fs::read_to_string(path)
+      .map_err(|e| e.to_string())
+      .and_then(|text| text.parse::<Scenario>().map_err(|e| e.to_string()))
+      .unwrap_or_else(|e| fail(&format!("{}: {}", path, e))),
+    None => options.scenario(),
   };
-  let min_particle = Particle {
-    id: usize::MAX,
-    x: Vector((-5., -5.)),
-    v: Vector((-1., -1.)),
-    m: 1.,
-    r: 1.0
+
+  let mut rng = match scenario.seed {
+    Some(seed) => SeededRng::new(seed),
+    None => SeededRng::from_os().unwrap_or_else(|e| fail(&format!("unable to seed: {}", e))),
   };
-  let mut rng = StdRng::new().unwrap();
+  info!("seed: {}", rng.seed());
+  let init = scenario.build(&mut rng)
+    .unwrap_or_else(|e| fail(&format!("unable to place the particles: {}", e.message)));
 
-  let init_box = SpaceBox::new_random(&mut rng, NUM_PARTICLES, min_particle, max_particle);
-  let init = SpaceTime::new(init_box, Time(0.));
+  let mut measurements: Vec<Measurement> = scenario.observers.iter()
+    .map(|observation| Measurement::new(observation, &init, scenario.step))
+    .collect();
+  let count = init.space.particles().count();
+  let area = init.space.bounds().area();
+  let (kt, eta) = (temperature(&init.space), packing_fraction(&init.space, area));
+  let mut outputs: Vec<Output> = scenario.outputs.iter()
+    .map(|&(format, ref path)| Output::open(format, path, scenario.step, rng.seed())
+      .unwrap_or_else(|e| fail(&format!("unable to open {}: {}", path, e))))
+    .collect();
 
   info!("starting");
   debug!("first state: {:?}", init);
 
-  init.every(STEP)
-    .take(100)
-    .map(|s| {
+  let report = {
+    let mut simulation = Simulation::new(init, scenario.step).with_seed(rng.seed());
+    for measurement in &mut measurements {
+      simulation = simulation.with_observer(measurement.observer());
+    }
+
+    for _ in 0..scenario.length.frames(scenario.step) {
+      let s = match simulation.step() {
+        Some(s) => s,
+        None => break
+      };
       debug!("t: {:?}", s.time);
       if let Some((p1, p2)) = s.space.space_vec().particle_pairs()
-        .find(|&(ref p1, ref p2)| p1.overlaps(p2)) {
+        .find(|&(p1, p2)| p1.overlaps(p2)) {
         warn!("found overlapping particles:\n{:?}\n{:?}", p1, p2);
       }
 
-      let p_str: String = s.space.particles()
-        .map(|p| format!("{}\t{}\t{}", (p.x.0).0, (p.x.0).1, p.r))
-        .collect::<Vec<_>>()
-        .join("\n");
-      format!("{}\n\n", p_str)
-    })
-    .inspect(|s| println!("{}", s))
-    .last();
+      for output in &mut outputs {
+        output.write(&s).unwrap_or_else(|e| fail(&format!("unable to write frame: {}", e)));
+      }
+    }
+    simulation.report()
+  };
+  for output in outputs {
+    output.finish(scenario.step).unwrap_or_else(|e| fail(&format!("unable to finish output: {}", e)));
+  }
 
+  for measurement in &measurements {
+    measurement.log();
+  }
+  for (name, estimate) in report {
+    info!("{}: {} +/- {}", name, estimate.value, estimate.error);
+  }
+  info!("ideal gas pressure: {}, Henderson pressure: {}",
+    ideal_gas_pressure(count, kt, area),
+    henderson_pressure(count, kt, area, eta));
   info!("ending");
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn args(s: &str) -> Result<Options, String> {
+    Options::parse(s.split_whitespace().map(|a| a.to_string()))
+  }
+
+  #[test]
+  fn options_override_defaults() {
+    let options = args("-n 20 --box 30,20 --radius 0.5,0.5 --until 2 -f xyz -o run.xyz -v").unwrap();
+    let expected = Options {
+      count: 20,
+      size: (30., 20.),
+      radius: (0.5, 0.5),
+      length: Length::Until(Time(2.)),
+      format: Format::Xyz,
+      output: "run.xyz".to_string(),
+      log_level: log::LogLevelFilter::Debug,
+      .. Options::default()
+    };
+    assert!(options == expected, "{:?} did not equal {:?}", options, expected);
+    // t = 0, 0.1, ..., 2
+    let scenario = options.scenario();
+    assert!(scenario.length.frames(scenario.step) == 21, "{:?}", scenario.length);
+  }
+
+  #[test]
+  fn invalid_options_are_rejected() {
+    for invalid in &["--count", "--count six", "--radius 1,0.5", "--box 1,1", "-f bmp", "-f png", "--step 0", "--fraction 1",
+                     "-s run.txt -n 5", "--seed 1 --scenario run.txt", "--fraction 0.5 -n 5"] {
+      assert!(args(invalid).is_err(), "{} was accepted", invalid);
+    }
+  }
+}
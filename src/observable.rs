@@ -0,0 +1,50 @@
+use super::{custom_float, CustomFloat, Space};
+
+/// A measured quantity and the standard error of the measurement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+  pub value: CustomFloat,
+  pub error: CustomFloat,
+}
+
+impl Estimate {
+  /// Estimates the mean of a quantity from its averages over equal, and
+  /// sufficiently long, blocks of time, so that the blocks are uncorrelated.
+  /// The error is infinite unless there are at least two blocks.
+  pub fn from_blocks(blocks: &[CustomFloat]) -> Estimate {
+    let n = blocks.len() as CustomFloat;
+    let mean = if blocks.is_empty() { 0. } else { blocks.iter().sum::<CustomFloat>() / n };
+    let error = if blocks.len() < 2 {
+      CustomFloat::INFINITY
+    } else {
+      let var = blocks.iter().map(|b| (b - mean) * (b - mean)).sum::<CustomFloat>() / (n - 1.);
+      (var / n).sqrt()
+    };
+    Estimate { value: mean, error }
+  }
+}
+
+/// Computes the temperature of the particles in `space`, in units where
+/// Boltzmann's constant is 1. In 2D, each particle has a mean kinetic
+/// energy of kT.
+pub fn temperature<S: Space>(space: &S) -> CustomFloat {
+  let n = space.particles().len() as CustomFloat;
+  space.particles().map(|p| 0.5 * p.m * p.v.norm2()).sum::<CustomFloat>() / n
+}
+
+/// Computes the fraction of `area` covered by the particles in `space`.
+pub fn packing_fraction<S: Space>(space: &S, area: CustomFloat) -> CustomFloat {
+  space.particles().map(|p| custom_float::consts::PI * p.r * p.r).sum::<CustomFloat>() / area
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn estimate_from_blocks() {
+    let estimate = Estimate::from_blocks(&[1., 2., 3., 4.]);
+    assert!(estimate.value == 2.5, "{:?}", estimate);
+    assert!((estimate.error - (5. / 12. as CustomFloat).sqrt()).abs() < 1e-12, "{:?}", estimate);
+  }
+}
@@ -0,0 +1,252 @@
+use std::io::{self, Read, Write};
+use super::{Collision, ConservationMonitor, Estimate, FreePaths, MeanSquaredDisplacement,
+  RadialDistribution, Space, SpaceBox, SpaceTime, Time, VelocityAutocorrelation,
+  VelocityDistribution, VirialPressure, WallPressure};
+use super::checkpoint::{read_checkpoint, write_checkpoint, Checkpoint, CheckpointError,
+  CheckpointReader, CheckpointWriter};
+use super::spacetime::SpaceTimeStepIterator;
+
+/// Callbacks for the collisions and sampled states of a `Simulation`.
+///
+/// Every method does nothing by default. Collisions are passed to
+/// `on_collision`, which calls the method for their kind.
+pub trait Observer<S: Space> {
+  /// Called with each collision, and the absolute time at which it occurs.
+  fn on_collision(&mut self, time: Time, collision: &Collision) {
+    match *collision {
+      Collision::Free => (),
+      Collision::Wall { .. } => self.on_wall(time, collision),
+      Collision::Wrap { .. } => self.on_wrap(time, collision),
+      Collision::Exit { .. } => self.on_exit(time, collision),
+      Collision::Bounce { .. } => self.on_bounce(time, collision),
+      Collision::Cell { .. } => self.on_cell(time, collision),
+    }
+  }
+
+  fn on_bounce(&mut self, _time: Time, _collision: &Collision) {}
+  fn on_wall(&mut self, _time: Time, _collision: &Collision) {}
+  fn on_wrap(&mut self, _time: Time, _collision: &Collision) {}
+  fn on_exit(&mut self, _time: Time, _collision: &Collision) {}
+  fn on_cell(&mut self, _time: Time, _collision: &Collision) {}
+
+  /// Called with the state at the end of each step.
+  fn on_sample(&mut self, _s: &SpaceTime<S>) {}
+
+  /// Reports named estimates from everything observed up to `now`.
+  fn estimates(&self, _now: Time) -> Vec<(String, Estimate)> {
+    Vec::new()
+  }
+}
+
+impl<S: Space> Observer<S> for ConservationMonitor {
+  fn on_sample(&mut self, s: &SpaceTime<S>) {
+    self.check(s);
+  }
+}
+
+impl<S: Space> Observer<S> for VelocityDistribution {
+  fn on_sample(&mut self, s: &SpaceTime<S>) {
+    self.sample(s);
+  }
+}
+
+impl<S: Space> Observer<S> for MeanSquaredDisplacement {
+  fn on_sample(&mut self, s: &SpaceTime<S>) {
+    self.sample(s);
+  }
+}
+
+impl Observer<SpaceBox> for RadialDistribution {
+  fn on_sample(&mut self, s: &SpaceTime<SpaceBox>) {
+    self.sample(s);
+  }
+}
+
+impl<S: Space> Observer<S> for WallPressure {
+  fn on_wall(&mut self, time: Time, collision: &Collision) {
+    self.record(time, collision);
+  }
+
+  fn estimates(&self, now: Time) -> Vec<(String, Estimate)> {
+    self.report(now)
+  }
+}
+
+impl<S: Space> Observer<S> for VirialPressure {
+  fn on_collision(&mut self, time: Time, collision: &Collision) {
+    self.record(time, collision);
+  }
+
+  fn estimates(&self, now: Time) -> Vec<(String, Estimate)> {
+    self.report(now)
+  }
+}
+
+impl<S: Space> Observer<S> for VelocityAutocorrelation {
+  fn on_collision(&mut self, time: Time, collision: &Collision) {
+    self.record(time, collision);
+  }
+
+  fn estimates(&self, now: Time) -> Vec<(String, Estimate)> {
+    self.report(now)
+  }
+}
+
+impl<S: Space> Observer<S> for FreePaths {
+  fn on_collision(&mut self, time: Time, collision: &Collision) {
+    self.record(time, collision);
+  }
+
+  fn estimates(&self, now: Time) -> Vec<(String, Estimate)> {
+    self.report(now)
+  }
+}
+
+/// Runs a simulation in fixed steps, as `SpaceTime::every` does, passing
+/// every collision and the state after every step to each of its observers
+/// in order of time.
+pub struct Simulation<'a, S: Space> {
+  steps: SpaceTimeStepIterator<S>,
+  time: Time,
+  observers: Vec<&'a mut dyn Observer<S>>,
+}
+
+impl<'a, S: Space + Clone> Simulation<'a, S> {
+  pub fn new(init: SpaceTime<S>, step: Time) -> Simulation<'a, S> {
+    Simulation {
+      time: init.time,
+      steps: init.every(step),
+      observers: Vec::new(),
+    }
+  }
+
+  pub fn with_observer(mut self, observer: &'a mut dyn Observer<S>) -> Simulation<'a, S> {
+    self.observers.push(observer);
+    self
+  }
+
+  /// Records the seed of the random initial state, as
+  /// `SpaceTimeStepIterator::with_seed` does.
+  pub fn with_seed(self, seed: usize) -> Simulation<'a, S> {
+    Simulation { steps: self.steps.with_seed(seed), .. self }
+  }
+
+  /// Returns the seed given to `with_seed`, if any.
+  pub fn seed(&self) -> Option<usize> {
+    self.steps.seed()
+  }
+
+  /// Returns the time of the last state sampled.
+  pub fn time(&self) -> Time {
+    self.time
+  }
+
+  /// Samples the next state, dispatching the collisions leading up to it,
+  /// and returns it.
+  pub fn step(&mut self) -> Option<SpaceTime<S>> {
+    // the step iterator processes collisions a step ahead,
+    // so hold them back until the state before them is sampled
+    let mut collisions = Vec::new();
+    let s = self.steps.next_with(|time, collision| collisions.push((time, collision.clone())));
+
+    if let Some(ref s) = s {
+      self.time = s.time;
+      for observer in self.observers.iter_mut() {
+        observer.on_sample(s);
+      }
+    }
+    for observer in self.observers.iter_mut() {
+      for &(time, ref collision) in &collisions {
+        observer.on_collision(time, collision);
+      }
+    }
+    s
+  }
+
+  /// Samples `count` states, returning the last of them.
+  pub fn run(&mut self, count: usize) -> Option<SpaceTime<S>> {
+    (0..count).fold(None, |_, _| self.step())
+  }
+
+  /// Reports the estimates of every observer up to the last state sampled.
+  pub fn report(&self) -> Vec<(String, Estimate)> {
+    self.observers.iter()
+      .flat_map(|o| o.estimates(self.time))
+      .collect()
+  }
+}
+
+impl<'a> Simulation<'a, SpaceBox> {
+  /// Saves the simulation to `out`, from which `load` resumes it exactly,
+  /// as `SpaceTimeStepIterator::save` does. Observers are not saved, and
+  /// should be added again to the resumed simulation.
+  pub fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+    write_checkpoint(out, self)
+  }
+
+  pub fn load(input: &mut dyn Read) -> Result<Simulation<'a, SpaceBox>, CheckpointError> {
+    read_checkpoint(input)
+  }
+}
+
+impl<'a, S: Space + Checkpoint> Checkpoint for Simulation<'a, S> {
+  fn save_to(&self, w: &mut CheckpointWriter) {
+    w.line("sampled");
+    self.time.save_to(w);
+    self.steps.save_to(w);
+  }
+
+  fn load_from(r: &mut CheckpointReader) -> Result<Simulation<'a, S>, CheckpointError> {
+    r.expect("sampled")?;
+    let time = Time::load_from(r)?;
+    Ok(Simulation {
+      time,
+      steps: SpaceTimeStepIterator::load_from(r)?,
+      observers: Vec::new(),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{SpaceBox, WallPressure};
+  use super::super::fixtures::bouncing_between_walls;
+
+  #[derive(Default)]
+  struct Log {
+    walls: Vec<Time>,
+    samples: Vec<Time>,
+  }
+
+  impl Observer<SpaceBox> for Log {
+    fn on_wall(&mut self, time: Time, _collision: &Collision) {
+      self.walls.push(time);
+    }
+
+    fn on_sample(&mut self, s: &SpaceTime<SpaceBox>) {
+      self.samples.push(s.time);
+    }
+  }
+
+  #[test]
+  fn observers_see_collisions_and_samples() {
+    let space = bouncing_between_walls();
+    let mut pressure = WallPressure::new(&space, Time(0.), Time(16.));
+    let mut log = Log::default();
+    {
+      let mut simulation = Simulation::new(SpaceTime::new(space, Time(0.)), Time(3.))
+        .with_observer(&mut pressure)
+        .with_observer(&mut log);
+      simulation.run(6);
+    }
+
+    assert!(log.samples == vec![Time(0.), Time(3.), Time(6.), Time(9.), Time(12.), Time(15.)],
+      "{:?}", log.samples);
+    // the collisions up to the step after the last sample have been seen
+    assert!(log.walls == vec![Time(4.), Time(12.)], "{:?}", log.walls);
+    let report = pressure.report(Time(16.));
+    assert!(report.iter().any(|&(ref name, e)| name == "wall pressure (right)" && e.value == 0.025),
+      "{:?}", report);
+  }
+}
@@ -0,0 +1,471 @@
+use std::cmp::Ordering;
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+use rand::Rng;
+use rand::distributions::{IndependentSample, Normal};
+use super::custom_float::consts::PI;
+use super::{BoundedRand, Bounds, CellList, CustomFloat, Particle, SeededRng, Vector};
+
+/// The number of random positions tried for each particle before giving up.
+pub const MAX_ATTEMPTS: usize = 10_000;
+
+/// Above this packing fraction, particles are placed on a lattice rather
+/// than at random, which rarely reaches it.
+const RANDOM_FRACTION: CustomFloat = 0.5;
+
+/// The number of times each particle is displaced to melt a lattice.
+const MELT_SWEEPS: usize = 100;
+
+/// Why particles could not be placed in a box.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InitError {
+  /// Only `placed` of `count` particles were placed at random, before
+  /// `MAX_ATTEMPTS` positions for the next all overlapped others.
+  Attempts { placed: usize, count: usize },
+  /// A lattice spaced for the largest of `count` particles has only
+  /// `sites` sites for them, at a packing fraction of `fraction`.
+  TooDense { count: usize, sites: usize, fraction: CustomFloat },
+  /// The request itself is impossible, such as a packing fraction of 1.
+  Invalid(String),
+}
+
+impl fmt::Display for InitError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      InitError::Attempts { placed, count } => write!(
+        f, "placed only {} of {} particles at random, after {} attempts at the next",
+        placed, count, MAX_ATTEMPTS),
+      InitError::TooDense { count, sites, fraction } => write!(
+        f, "{} particles at a packing fraction of {:.3} do not fit: a lattice spaced for the \
+            largest of them has only {} sites", count, fraction, sites),
+      InitError::Invalid(ref msg) => write!(f, "{}", msg),
+    }
+  }
+}
+
+impl error::Error for InitError {}
+
+/// A regular arrangement of sites for particles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lattice {
+  /// Rows and columns of sites.
+  Square,
+  /// Rows of sites with every other row offset by half a spacing, so that
+  /// each site has six nearest neighbours. It is the densest packing of
+  /// equal disks.
+  Triangular,
+}
+
+impl Lattice {
+  /// Returns the sites of the lattice with at least `spacing` between
+  /// neighbours, which keep particles of radius `r` inside `bounds`. Along
+  /// an axis which is not periodic, the lattice is centred; along a periodic
+  /// axis, it is stretched to fit a whole number of spacings.
+  pub fn sites(&self, bounds: &Bounds, spacing: CustomFloat, r: CustomFloat) -> Vec<Vector> {
+    let Vector((left, bottom)) = *bounds.bottom_left();
+    let Vector((right, top)) = *bounds.top_right();
+    let (px, py) = bounds.periodic();
+    let (columns, dx) = axis(left, right, spacing, r, px, false);
+    let (rows, _) = match *self {
+      Lattice::Square => axis(bottom, top, spacing, r, py, false),
+      // alternate rows are offset, so across a periodic axis there must be
+      // an even number of them
+      Lattice::Triangular => axis(bottom, top, spacing * (3. as CustomFloat).sqrt() / 2., r, py, true),
+    };
+
+    let mut sites = Vec::with_capacity(columns.len() * rows.len());
+    for (j, &y) in rows.iter().enumerate() {
+      let offset = if *self == Lattice::Triangular && j % 2 == 1 { dx / 2. } else { 0. };
+      for &x in &columns {
+        let site = Particle::new(0, wrap(bounds, Vector((x + offset, y))), Vector((0., 0.)), r, 1.);
+        if fits(bounds, &site) {
+          sites.push(site.x);
+        }
+      }
+    }
+    sites
+  }
+}
+
+impl FromStr for Lattice {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Lattice, String> {
+    match s {
+      "square" => Ok(Lattice::Square),
+      "triangular" | "hexagonal" => Ok(Lattice::Triangular),
+      _ => Err(format!("unknown lattice `{}`", s)),
+    }
+  }
+}
+
+/// How the velocities of newly placed particles are drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Velocities {
+  /// At rest.
+  Zero,
+  /// With each component uniformly within the range.
+  Uniform(CustomFloat, CustomFloat),
+  /// From the Maxwell–Boltzmann distribution at the temperature kT. The
+  /// total momentum is then removed, and the velocities scaled so that the
+  /// temperature is exactly kT.
+  Temperature(CustomFloat),
+}
+
+impl Velocities {
+  /// Draws the velocities of `ps`.
+  ///
+  /// # Panics
+  /// - if the temperature is negative
+  pub fn assign(&self, rng: &mut SeededRng, ps: &mut [Particle]) {
+    match *self {
+      Velocities::Zero => for p in ps.iter_mut() {
+        p.v = Vector((0., 0.));
+      },
+      Velocities::Uniform(min, max) => for p in ps.iter_mut() {
+        p.v = BoundedRand::rand(rng, &Vector((min, min)), &Vector((max, max)));
+      },
+      Velocities::Temperature(kt) => {
+        assert!(kt >= 0., "the temperature must not be negative, not {}", kt);
+        for p in ps.iter_mut() {
+          let normal = Normal::new(0., (kt / p.m).sqrt());
+          p.v = Vector((normal.ind_sample(rng), normal.ind_sample(rng)));
+        }
+        let mass: CustomFloat = ps.iter().map(|p| p.m).sum();
+        let momentum = ps.iter().fold(Vector((0., 0.)), |total, p| &total + &p.v.scale(p.m));
+        let drift = momentum.scale(1. / mass);
+        for p in ps.iter_mut() {
+          p.v = &p.v - &drift;
+        }
+        // in 2D, each particle has a mean kinetic energy of kT
+        let energy: CustomFloat = ps.iter().map(|p| 0.5 * p.m * p.v.norm2()).sum();
+        if energy > 0. {
+          let scale = (kt * ps.len() as CustomFloat / energy).sqrt();
+          for p in ps.iter_mut() {
+            p.v = p.v.scale(scale);
+          }
+        }
+      },
+    }
+  }
+}
+
+/// Returns whether `p` lies inside `bounds`, including its radius along
+/// axes which are not periodic.
+pub fn fits(bounds: &Bounds, p: &Particle) -> bool {
+  let Vector((left, bottom)) = *bounds.bottom_left();
+  let Vector((right, top)) = *bounds.top_right();
+  let Vector((x, y)) = p.x;
+  let (px, py) = bounds.periodic();
+  let inside = |x: CustomFloat, lo: CustomFloat, hi: CustomFloat, periodic: bool|
+    if periodic { lo <= x && x < hi } else { lo + p.r <= x && x <= hi - p.r };
+  inside(x, left, right, px) && inside(y, bottom, top, py)
+}
+
+/// Moves `x` into `bounds` along its periodic axes.
+fn wrap(bounds: &Bounds, x: Vector) -> Vector {
+  let Vector((left, bottom)) = *bounds.bottom_left();
+  let Vector((wx, wy)) = bounds.top_right() - bounds.bottom_left();
+  let Vector((x, y)) = x;
+  let (px, py) = bounds.periodic();
+  Vector((
+    if px { left + (x - left).rem_euclid(wx) } else { x },
+    if py { bottom + (y - bottom).rem_euclid(wy) } else { y }
+  ))
+}
+
+fn overlaps(bounds: &Bounds, p: &Particle, q: &Particle) -> bool {
+  // allowing for rounding, so that neighbours on a lattice may touch
+  bounds.separation(&p.x, &q.x).norm() < p.r + q.r - 1e-9
+}
+
+/// Returns cells holding `ps`, at least `size` wide, so that particles
+/// closer than `size` are in the same or neighbouring cells. The cells are
+/// widened if need be so that there are no more of them than `count`.
+fn cells_for(bounds: &Bounds, ps: &[Particle], size: CustomFloat, count: usize) -> CellList {
+  let size = size.max((bounds.area() / count.max(1) as CustomFloat).sqrt());
+  CellList::new(bounds, size, ps.iter())
+}
+
+/// Draws particles between `min` and `max` until they cover `area`, as
+/// nearly as whole particles allow. Their positions are left to be placed.
+///
+/// # Panics
+/// - if `min` has a radius of zero
+pub fn draw_covering(rng: &mut SeededRng, min: &Particle, max: &Particle, area: CustomFloat)
+                     -> Vec<Particle> {
+  assert!(min.r > 0., "particles must have a positive radius to cover an area");
+  let (mut ps, mut covered) = (Vec::new(), 0.);
+  loop {
+    let p: Particle = BoundedRand::rand(rng, min, max);
+    let a = PI * p.r * p.r;
+    // stop at whichever number of particles comes nearest the area
+    if covered + a / 2. > area { return ps; }
+    covered += a;
+    ps.push(p);
+  }
+}
+
+/// Places each of `ps` inside `bounds`, without overlapping each other or
+/// any of `fixed`, and returns them in order. Only their positions change.
+///
+/// Below a packing fraction of 0.5, each particle is tried at up to
+/// `MAX_ATTEMPTS` random positions. Denser systems, and any which random
+/// placement gives up on, are seeded on a triangular lattice, spread as
+/// widely as leaves room for the largest particle at every site, and then
+/// melted by random displacements.
+pub fn place(rng: &mut SeededRng, bounds: &Bounds, fixed: &[Particle], ps: Vec<Particle>)
+             -> Result<Vec<Particle>, InitError> {
+  if ps.is_empty() {
+    return Ok(ps);
+  }
+  let fraction = fixed.iter().chain(ps.iter()).map(|p| PI * p.r * p.r).sum::<CustomFloat>()
+    / bounds.area();
+  if fraction <= RANDOM_FRACTION {
+    match place_randomly(rng, bounds, fixed, ps.clone()) {
+      Ok(placed) => return Ok(placed),
+      Err(e) => debug!("falling back to a lattice: {}", e),
+    }
+  }
+
+  let r_max = ps.iter().fold(0., |r, p| p.r.max(r));
+  let mut sites = clear_sites(bounds, fixed, Lattice::Triangular, 2. * r_max, r_max);
+  if sites.len() < ps.len() {
+    return Err(InitError::TooDense { count: ps.len(), sites: sites.len(), fraction });
+  }
+  // spread the lattice as widely as leaves a site for each particle, since
+  // touching particles have no room to move
+  let Vector((wx, wy)) = bounds.top_right() - bounds.bottom_left();
+  let (mut lo, mut hi) = (2. * r_max, wx.max(wy));
+  for _ in 0..40 {
+    let spacing = (lo + hi) / 2.;
+    let wider = clear_sites(bounds, fixed, Lattice::Triangular, spacing, r_max);
+    if wider.len() >= ps.len() {
+      lo = spacing;
+      sites = wider;
+    } else {
+      hi = spacing;
+    }
+  }
+  let ps = fill_sites(rng, sites, ps);
+  Ok(melt(rng, bounds, fixed, ps))
+}
+
+/// Places each of `ps` at a random site of `lattice`, `spacing` apart,
+/// inside `bounds` and clear of `fixed`.
+pub fn place_on_lattice(rng: &mut SeededRng, bounds: &Bounds, fixed: &[Particle], ps: Vec<Particle>,
+                        lattice: Lattice, spacing: CustomFloat) -> Result<Vec<Particle>, InitError> {
+  let r_max = ps.iter().fold(0., |r, p| p.r.max(r));
+  if spacing.partial_cmp(&(2. * r_max)).is_none_or(Ordering::is_lt) {
+    return Err(InitError::Invalid(format!(
+      "a lattice spacing of {} is too small for particles of radius {}", spacing, r_max)));
+  }
+  let sites = clear_sites(bounds, fixed, lattice, spacing, r_max);
+  if sites.len() < ps.len() {
+    let fraction = ps.iter().map(|p| PI * p.r * p.r).sum::<CustomFloat>() / bounds.area();
+    return Err(InitError::TooDense { count: ps.len(), sites: sites.len(), fraction });
+  }
+  Ok(fill_sites(rng, sites, ps))
+}
+
+/// Returns the sites of `lattice` which leave room for a particle of
+/// radius `r` clear of `fixed`.
+fn clear_sites(bounds: &Bounds, fixed: &[Particle], lattice: Lattice, spacing: CustomFloat,
+               r: CustomFloat) -> Vec<Vector> {
+  let r_max = fixed.iter().fold(0., |r, q| q.r.max(r));
+  let cells = cells_for(bounds, fixed, r + r_max, fixed.len());
+  lattice.sites(bounds, spacing, r).into_iter()
+    .filter(|x| !cells.near(x).into_iter().any(|j| {
+      let q = &fixed[j];
+      bounds.separation(x, &q.x).norm() < r + q.r - 1e-9
+    }))
+    .collect()
+}
+
+/// Moves each of `ps` to one of `sites`, chosen at random so that any
+/// vacancies are scattered through the lattice.
+fn fill_sites(rng: &mut SeededRng, mut sites: Vec<Vector>, ps: Vec<Particle>) -> Vec<Particle> {
+  rng.shuffle(&mut sites);
+  ps.into_iter().zip(sites).map(|(p, x)| Particle { x, .. p }).collect()
+}
+
+/// Places particles one at a time at uniformly random positions.
+pub fn place_randomly(rng: &mut SeededRng, bounds: &Bounds, fixed: &[Particle], ps: Vec<Particle>)
+                      -> Result<Vec<Particle>, InitError> {
+  let count = ps.len();
+  let Vector((left, bottom)) = *bounds.bottom_left();
+  let Vector((right, top)) = *bounds.top_right();
+  let (px, py) = bounds.periodic();
+  let r_max = fixed.iter().chain(ps.iter()).fold(0., |r, p| p.r.max(r));
+  // the particles placed so far follow those fixed, in the same cells
+  let mut placed: Vec<Particle> = fixed.to_vec();
+  let mut cells = cells_for(bounds, fixed, 2. * r_max, fixed.len() + count);
+
+  for p in ps {
+    let inset = |periodic: bool| if periodic { 0. } else { p.r };
+    let (ix, iy) = (inset(px), inset(py));
+    if left + ix > right - ix || bottom + iy > top - iy {
+      return Err(InitError::Invalid(format!("particles of radius {} do not fit in the box", p.r)));
+    }
+    let (lower, upper) = (Vector((left + ix, bottom + iy)), Vector((right - ix, top - iy)));
+    let position = (0..MAX_ATTEMPTS)
+      .map(|_| Particle { x: wrap(bounds, BoundedRand::rand(rng, &lower, &upper)), .. p.clone() })
+      .find(|q| !cells.near(&q.x).into_iter().any(|j| overlaps(bounds, q, &placed[j])));
+    match position {
+      Some(q) => {
+        cells.add_particle(&q.x);
+        placed.push(q);
+      },
+      None => return Err(InitError::Attempts { placed: placed.len() - fixed.len(), count }),
+    }
+  }
+  Ok(placed.split_off(fixed.len()))
+}
+
+/// Returns positions along an axis from `lo` to `hi` at least `spacing`
+/// apart, which keep particles of radius `r` inside, and the spacing
+/// between them, with an `even` number of them if the axis is periodic.
+fn axis(lo: CustomFloat, hi: CustomFloat, spacing: CustomFloat, r: CustomFloat, periodic: bool,
+            even: bool) -> (Vec<CustomFloat>, CustomFloat) {
+  let width = hi - lo;
+  if periodic {
+    let mut n = (width / spacing).floor() as usize;
+    if even && n % 2 == 1 { n -= 1; }
+    let step = width / n.max(1) as CustomFloat;
+    ((0..n).map(|i| lo + (i as CustomFloat + 0.5) * step).collect(), step)
+  } else if width >= 2. * r {
+    let n = ((width - 2. * r) / spacing).floor() as usize + 1;
+    let margin = (width - 2. * r - (n - 1) as CustomFloat * spacing) / 2.;
+    ((0..n).map(|i| lo + r + margin + i as CustomFloat * spacing).collect(), spacing)
+  } else {
+    (Vec::new(), spacing)
+  }
+}
+
+/// Displaces each particle at random, many times over, rejecting any move
+/// which would overlap another particle or leave `bounds`, so that the
+/// particles lose the order they started in. The largest displacement is
+/// tuned so that about half of the moves are accepted.
+fn melt(rng: &mut SeededRng, bounds: &Bounds, fixed: &[Particle], mut ps: Vec<Particle>)
+        -> Vec<Particle> {
+  let Vector((wx, wy)) = bounds.top_right() - bounds.bottom_left();
+  let mut delta = ps.iter().fold(0., |r, p| p.r.max(r));
+  // the particles follow those fixed, in the same cells
+  let n = fixed.len();
+  let mut all = fixed.to_vec();
+  all.append(&mut ps);
+  let r_max = all.iter().fold(0., |r, p| p.r.max(r));
+  let mut cells = cells_for(bounds, &all, 2. * r_max, all.len());
+  for _ in 0..MELT_SWEEPS {
+    let mut accepted = 0;
+    for i in n..all.len() {
+      let step = Vector((rng.gen_range(-delta, delta), rng.gen_range(-delta, delta)));
+      let moved = Particle { x: wrap(bounds, &all[i].x + &step), .. all[i].clone() };
+      let clear = fits(bounds, &moved) &&
+        !cells.near(&moved.x).into_iter().any(|j| j != i && overlaps(bounds, &moved, &all[j]));
+      if clear {
+        cells.move_particle(i, cells.locate(&moved.x));
+        all[i] = moved;
+        accepted += 1;
+      }
+    }
+    let rate = accepted as CustomFloat / (all.len() - n).max(1) as CustomFloat;
+    delta = (delta * if rate < 0.4 { 0.8 } else if rate > 0.6 { 1.25 } else { 1. }).min(wx.min(wy) / 2.);
+  }
+  all.split_off(n)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{packing_fraction, temperature, Boundary, SpaceBox, Space};
+
+  fn particle(r: CustomFloat) -> Particle {
+    Particle::new(0, Vector((0., 0.)), Vector((0., 0.)), r, 1.)
+  }
+
+  #[test]
+  fn dense_systems_are_placed_on_a_melted_lattice() {
+    let bounds = Bounds::new(Vector((10., 10.)), Vector((0., 0.)));
+    // a packing fraction of about 0.7, which random placement rarely reaches
+    let ps = vec![particle(0.5); 89];
+    let mut rng = SeededRng::new(3);
+    let placed = place(&mut rng, &bounds, &[], ps).unwrap();
+
+    assert!(placed.len() == 89);
+    assert!(placed.iter().all(|p| fits(&bounds, p)), "{:?}", placed);
+    for (i, p) in placed.iter().enumerate() {
+      for q in &placed[i + 1..] {
+        assert!(!overlaps(&bounds, p, q), "{:?} overlaps {:?}", p, q);
+      }
+    }
+    let space = SpaceBox::new(placed, Vector((0., 0.)), Vector((10., 10.)));
+    assert!(space.particles().len() == 89);
+  }
+
+  #[test]
+  fn melting_moves_particles_off_their_sites() {
+    let bounds = Bounds::new(Vector((10., 10.)), Vector((0., 0.)));
+    let sites = Lattice::Triangular.sites(&bounds, 1.05, 0.5);
+    let ps: Vec<Particle> = sites.iter()
+      .map(|x| Particle { x: *x, .. particle(0.5) })
+      .collect();
+    let mut rng = SeededRng::new(3);
+    let melted = melt(&mut rng, &bounds, &[], ps);
+
+    assert!(melted.len() == sites.len(), "{} did not equal {}", melted.len(), sites.len());
+    for (i, p) in melted.iter().enumerate() {
+      assert!(fits(&bounds, p), "{:?} does not fit", p);
+      for q in &melted[i + 1..] {
+        assert!(!overlaps(&bounds, p, q), "{:?} overlaps {:?}", p, q);
+      }
+    }
+    // each is further from where it started than the gap between neighbours
+    let moved = melted.iter().zip(&sites).filter(|&(p, x)| (&p.x - x).norm() > 0.05).count();
+    assert!(4 * moved > 3 * sites.len(), "only {} of {} particles moved", moved, sites.len());
+  }
+
+  #[test]
+  fn impossible_packings_are_errors() {
+    let bounds = Bounds::new(Vector((10., 10.)), Vector((0., 0.))).with_boundary(Boundary::Periodic);
+    let mut rng = SeededRng::new(4);
+    let error = place(&mut rng, &bounds, &[], vec![particle(0.5); 120]).unwrap_err();
+    match error {
+      InitError::TooDense { count: 120, sites: 100, .. } => (),
+      _ => panic!("{:?} is not the error expected", error),
+    }
+
+    // two particles this large cannot both fit across a periodic box 10 wide
+    let error = place_randomly(&mut rng, &bounds, &[], vec![particle(4.); 2]).unwrap_err();
+    assert!(error == InitError::Attempts { placed: 1, count: 2 }, "{:?}", error);
+  }
+
+  #[test]
+  fn lattices_and_adsorption_place_particles_with_velocities() {
+    let bounds = Bounds::new(Vector((10., 10.)), Vector((0., 0.)));
+    let mut rng = SeededRng::new(5);
+    let square = SpaceBox::new_lattice(&mut rng, bounds.clone(), Lattice::Square, 1., &particle(0.5),
+                                       Velocities::Temperature(2.)).unwrap();
+    assert!(square.particles().len() == 100, "{}", square.particles().len());
+    let momentum = square.particles().fold(Vector((0., 0.)), |total, p| &total + &p.v.scale(p.m));
+    assert!(momentum.norm() < 1e-9, "{:?}", momentum);
+    assert!((temperature(&square) - 2.).abs() < 1e-9, "{}", temperature(&square));
+
+    // 6 rows of 10, and 5 offset rows with room for only 9
+    let sites = Lattice::Triangular.sites(&bounds, 1., 0.5);
+    assert!(sites.len() == 105, "{}", sites.len());
+
+    let adsorbed = SpaceBox::new_adsorbed(&mut rng, bounds.clone(), 0.3, &particle(0.5), &particle(0.5),
+                                          Velocities::Uniform(-1., 1.)).unwrap();
+    let fraction = packing_fraction(&adsorbed, bounds.area());
+    assert!((fraction - 0.3).abs() < 0.01, "{}", fraction);
+    // beyond the jamming limit
+    let error = SpaceBox::new_adsorbed(&mut rng, bounds, 0.7, &particle(0.5), &particle(0.5),
+                                       Velocities::Zero).unwrap_err();
+    match error {
+      InitError::Attempts { count: 89, .. } => (),
+      _ => panic!("{:?} is not the error expected", error),
+    }
+  }
+}
@@ -1,4 +1,4 @@
-use super::{custom_float, CustomFloat, Time};
+use super::{CustomFloat, Restitution, Time};
 use super::vector::Vector;
 
 #[derive(Clone, Debug)]
@@ -8,6 +8,9 @@ pub struct Particle {
   pub v: Vector,
   pub r: CustomFloat,
   pub m: CustomFloat,
+  /// How far the particle has moved since it was created. Unlike `x`,
+  /// this is not reset when the particle wraps through a periodic boundary.
+  pub displacement: Vector,
 }
 
 /// Computes solutions to the quadratic formula:
@@ -18,7 +21,7 @@ fn quadratic_formula(a: CustomFloat, b: CustomFloat, c: CustomFloat) -> Option<(
   // account for rounding error:
   // in the case of b = 4ac, rounding error may cause b < 4ac.
   // So, we increment the last bit in the mantissa by one
-  let b2 = b * b + (b / (2.0 as CustomFloat).powi((custom_float::MANTISSA_DIGITS - 1) as i32));
+  let b2 = b * b + (b / (2.0 as CustomFloat).powi((CustomFloat::MANTISSA_DIGITS - 1) as i32));
   let ac = 4. * a * c;
 
   if b2 < ac { None } // imaginary result
@@ -30,7 +33,43 @@ fn quadratic_formula(a: CustomFloat, b: CustomFloat, c: CustomFloat) -> Option<(
   }
 }
 
+/// Computes the time at which a coordinate starting at `x`, moving at `v`
+/// with constant acceleration `a`, reaches `target` while moving in the
+/// direction given by the sign of `dir`.
+/// Returns None if it never does so at a non-negative time.
+/// A coordinate at rest on `target`, accelerating in the direction of `dir`,
+/// arrives immediately.
+pub fn arrival_time(x: CustomFloat, v: CustomFloat, a: CustomFloat,
+                    target: CustomFloat, dir: CustomFloat) -> Option<CustomFloat> {
+  if a == 0. {
+    let t = (target - x) / v;
+    return if v * dir > 0. && t >= 0. { Some(t) } else { None };
+  }
+  if x == target && v == 0. {
+    return if a * dir > 0. { Some(0.) } else { None };
+  }
+
+  // solves a t^2 / 2 + v t + x - target = 0,
+  // where the velocity at each root is v + a t = -/+ sqrt(disc)
+  let disc = v * v - 2. * a * (x - target);
+  if disc < 0. { return None; }
+  let sq = disc.sqrt();
+
+  // avoid cancellation between v and sq
+  let t = if dir > 0. {
+    if v >= 0. { 2. * (target - x) / (v + sq) } else { (sq - v) / a }
+  } else {
+    if v >= 0. { -(v + sq) / a } else { 2. * (target - x) / (v - sq) }
+  };
+  if t >= 0. && sq > 0. { Some(t) } else { None }
+}
+
 impl Particle {
+  /// A particle which has not yet moved from `x`.
+  pub const fn new(id: usize, x: Vector, v: Vector, r: CustomFloat, m: CustomFloat) -> Particle {
+    Particle { id, x, v, r, m, displacement: Vector((0., 0.)) }
+  }
+
   pub fn overlaps(&self, other: &Particle) -> bool {
     let d = (&self.x - &other.x).norm();
     let r = self.r + other.r;
@@ -54,6 +93,10 @@ impl Particle {
     let a = dv.norm2();
     let b = 2. * ( dx * dv );
     let c = dx.norm2() - sr.powi(2);
+    if a == 0. {
+      // moving together, so the distance between them never changes
+      return None;
+    }
 
     let s = quadratic_formula(a, b, c);
     match s {
@@ -69,7 +112,7 @@ impl Particle {
           (&self.x - &other.x).norm(), self, other
         );
         assert!(
-          !(less < 0.),
+          less >= 0. || less.is_nan(),
           "impact_time found negative solution to quadratic formula:\n\
           solution: {:?}\n\
           distance: {:?}\n\
@@ -82,13 +125,20 @@ impl Particle {
     }
   }
 
-  /// Returns new particles after a collision.
+  /// Returns new particles after an elastic collision.
   /// Assumes that the particles are tangent to each other.
   /// The first particle returned corresponds to self.
   ///
   /// # Panics
   /// - if the two particles are not tangent (or within 1e-5 units)
   pub fn bounce(&self, other: &Particle) -> (Particle, Particle) {
+    self.bounce_with(other, &Restitution::elastic())
+  }
+
+  /// Returns new particles after a collision which retains the fraction of
+  /// their normal relative velocity given by `restitution`.
+  /// Otherwise behaves as `bounce`.
+  pub fn bounce_with(&self, other: &Particle, restitution: &Restitution) -> (Particle, Particle) {
     let r_t = self.r + other.r;
     let dx = &self.x - &other.x;
     // only works for particles in contact
@@ -103,9 +153,10 @@ impl Particle {
 
     let dv = &self.v - &other.v;
     let m_r = self.m * other.m / (self.m + other.m);
+    let e = restitution.at((&dv * &dx) / dx.norm());
 
-    // dp = 2 m1 m2 / (m1 + m2) (dv . \hat{dx}) \hat{dx}
-    let dp = dx.scale(2. * m_r * (&dv * &dx) / dx.norm2());
+    // dp = (1 + e) m1 m2 / (m1 + m2) (dv . \hat{dx}) \hat{dx}
+    let dp = dx.scale((1. + e) * m_r * (&dv * &dx) / dx.norm2());
     let v1 = &self.v - &dp.scale(1. / self.m);
     let v2 = &other.v + &dp.scale(1. / other.m);
 
@@ -116,12 +167,45 @@ impl Particle {
 
   pub fn evolve(&self, t: Time) -> Particle {
     let Time(t_) = t;
-    Particle { x: &self.x + &self.v.scale(t_), .. self.clone() }
+    let dx = self.v.scale(t_);
+    Particle {
+      x: &self.x + &dx,
+      displacement: &self.displacement + &dx,
+      .. self.clone()
+    }
+  }
+
+  /// Evolves the particle along the parabola due to the acceleration `g`.
+  pub fn evolve_under(&self, t: Time, g: &Vector) -> Particle {
+    if g.norm2() == 0. { return self.evolve(t); }
+
+    let Time(t_) = t;
+    let dx = &self.v.scale(t_) + &g.scale(0.5 * t_ * t_);
+    Particle {
+      x: &self.x + &dx,
+      v: &self.v + &g.scale(t_),
+      displacement: &self.displacement + &dx,
+      .. self.clone()
+    }
   }
 
   pub fn after_bounce(&self, other: &Particle, t: Time) -> (Particle, Particle) {
+    self.after_bounce_with(other, t, &Restitution::elastic())
+  }
+
+  pub fn after_bounce_with(&self, other: &Particle, t: Time, restitution: &Restitution)
+    -> (Particle, Particle) {
     let (prebounce1, prebounce2) = (self.evolve(t), other.evolve(t));
-    prebounce1.bounce(&prebounce2)
+    prebounce1.bounce_with(&prebounce2, restitution)
+  }
+
+  /// As `after_bounce_with`, but with both particles moving under the
+  /// acceleration `g`. Since `g` is uniform, it does not affect when
+  /// they impact.
+  pub fn after_bounce_under(&self, other: &Particle, t: Time, restitution: &Restitution,
+                            g: &Vector) -> (Particle, Particle) {
+    let (prebounce1, prebounce2) = (self.evolve_under(t, g), other.evolve_under(t, g));
+    prebounce1.bounce_with(&prebounce2, restitution)
   }
 }
 
@@ -132,20 +216,13 @@ impl PartialEq for Particle {
     self.x.eq(&other.x) &&
     self.v.eq(&other.v)
   }
-
-  fn ne(&self, other: &Particle) -> bool {
-    self.r.ne(&other.r) ||
-    self.m.ne(&other.m) ||
-    self.x.ne(&other.x) ||
-    self.v.ne(&other.v)
-  }
 }
 
 impl Eq for Particle {}
 
 #[cfg(test)]
 mod tests {
-  use super::super::{FloatOps, Particle, Time, Vector};
+  use super::super::{FloatOps, Particle, Restitution, Time, Vector};
 
   #[test]
   fn quadratic_formula_simple() {
@@ -157,41 +234,52 @@ mod tests {
 
   #[test]
   fn bounce_symmetrical_particles() {
-    let p1 = Particle {
-      id: 1,
-      x: Vector((-1., 0.)),
-      v: Vector((1., 0.)),
-      r: 1.,
-      m: 1.
-    };
-    let p2 = Particle {
-      id: 2,
-      x: Vector((1., 0.)),
-      v: Vector((-1., 0.)),
-      r: 1.,
-      m: 1.
-    };
+    let p1 = Particle::new(1, Vector((-1., 0.)), Vector((1., 0.)), 1., 1.);
+    let p2 = Particle::new(2, Vector((1., 0.)), Vector((-1., 0.)), 1., 1.);
     let (p1_, p2_) = p1.bounce(&p2);
     assert!((&p1_.v - &Vector((-1., 0.))).norm() < 1e-10);
     assert!((&p2_.v - &Vector((1., 0.))).norm() < 1e-10);
   }
 
+  #[test]
+  fn bounce_inelastic_particles() {
+    let p1 = Particle::new(1, Vector((-1., 0.)), Vector((1., 0.)), 1., 1.);
+    let p2 = Particle::new(2, Vector((1., 0.)), Vector((-1., 0.)), 1., 1.);
+    let (p1_, p2_) = p1.bounce_with(&p2, &Restitution::new(0.5, 0.));
+    assert!((&p1_.v - &Vector((-0.5, 0.))).norm() < 1e-10);
+    assert!((&p2_.v - &Vector((0.5, 0.))).norm() < 1e-10);
+
+    // the relative speed of 2 is below the cutoff
+    let (p1_, p2_) = p1.bounce_with(&p2, &Restitution::new(0.5, 3.));
+    assert!((&p1_.v - &Vector((-1., 0.))).norm() < 1e-10);
+    assert!((&p2_.v - &Vector((1., 0.))).norm() < 1e-10);
+  }
+
+  #[test]
+  fn arrival_time_under_acceleration() {
+    use super::arrival_time;
+    // thrown upwards at 2 under an acceleration of -1,
+    // reaching 1.5 on the way up at t = 1, and on the way down at t = 3
+    assert!(FloatOps(arrival_time(0., 2., -1., 1.5, 1.).unwrap()).close(&FloatOps(1.)));
+    assert!(FloatOps(arrival_time(0., 2., -1., 1.5, -1.).unwrap()).close(&FloatOps(3.)));
+    assert!(arrival_time(0., 2., -1., 3., 1.).is_none());
+
+    // at rest on a floor, but not on a ceiling
+    assert!(arrival_time(1., 0., -1., 1., -1.) == Some(0.));
+    assert!(arrival_time(1., 0., -1., 1., 1.).is_none());
+  }
+
+  #[test]
+  fn particles_moving_together_never_impact() {
+    let p1 = Particle::new(1, Vector((-2., 0.)), Vector((1., 1.)), 0.5, 1.);
+    let p2 = Particle::new(2, Vector((2., 0.)), Vector((1., 1.)), 0.5, 1.);
+    assert!(p1.impact_time(&p2).is_none(), "{:?} did not equal None", p1.impact_time(&p2));
+  }
+
   #[test]
   fn impact_time_symmetrical_partices() {
-    let p1 = Particle {
-      id: 1,
-      x: Vector((-2., 0.)),
-      v: Vector((1., 0.)),
-      r: 1.,
-      m: 1.
-    };
-    let p2 = Particle {
-      id: 2,
-      x: Vector((2., 0.)),
-      v: Vector((-1., 0.)),
-      r: 1.,
-      m: 1.
-    };
+    let p1 = Particle::new(1, Vector((-2., 0.)), Vector((1., 0.)), 1., 1.);
+    let p2 = Particle::new(2, Vector((2., 0.)), Vector((-1., 0.)), 1., 1.);
     let Time(t) = p1.impact_time(&p2).unwrap();
     assert!((t - 1.).abs() < 1e-10);
   }
@@ -0,0 +1,304 @@
+use super::{Boundary, Bounds, Collision, CustomFloat, Estimate, Particle, Side,
+  Space, SpaceBox, Time, Vector};
+
+const SIDES: [(Side, &str); 4] = [
+  (Side::Left, "left"),
+  (Side::Right, "right"),
+  (Side::Bottom, "bottom"),
+  (Side::Top, "top"),
+];
+
+/// Measures the pressure on each reflecting wall of a `SpaceBox`, i.e. the
+/// force per unit length, from the impulses given to it by the particles.
+///
+/// Impulses are summed over consecutive blocks of time, starting from
+/// `start`, and the pressure is averaged over the complete blocks, which
+/// should be long enough to include many collisions with each wall.
+#[derive(Debug, Clone)]
+pub struct WallPressure {
+  bounds: Bounds,
+  gravity: Vector,
+  start: Time,
+  block: Time,
+  // impulse given to each side, indexed by Side, in each block
+  impulses: Vec<[CustomFloat; 4]>,
+}
+
+impl WallPressure {
+  pub fn new(space: &SpaceBox, start: Time, block: Time) -> WallPressure {
+    assert!(block.0 > 0., "blocks must have a positive duration: {:?}", block);
+    WallPressure {
+      bounds: space.bounds().clone(),
+      gravity: *space.space_vec().gravity(),
+      start,
+      block,
+      impulses: Vec::new(),
+    }
+  }
+
+  /// Returns the side the particle hit, and the magnitude of the impulse
+  /// given to it, for a collision with a wall.
+  fn impulse(&self, t: Time, prev: &Particle, next: &Particle) -> (Side, CustomFloat) {
+    // the velocity on contact, which only changes normal to the wall
+    let Vector((vx, vy)) = prev.evolve_under(t, &self.gravity).v;
+    let Vector((nx, ny)) = next.v;
+    if nx != vx {
+      (if vx > 0. { Side::Right } else { Side::Left }, prev.m * (vx - nx).abs())
+    } else {
+      (if vy > 0. { Side::Top } else { Side::Bottom }, prev.m * (vy - ny).abs())
+    }
+  }
+
+  fn length(&self, side: Side) -> CustomFloat {
+    let Vector((wx, wy)) = self.bounds.top_right() - self.bounds.bottom_left();
+    match side {
+      Side::Left | Side::Right => wy,
+      Side::Bottom | Side::Top => wx,
+    }
+  }
+
+  /// Records the impulse of a collision with a wall, occurring at the
+  /// absolute time `time`. Other collisions are ignored.
+  pub fn record(&mut self, time: Time, collision: &Collision) {
+    if let &Collision::Wall { t, ref prev, ref next } = collision {
+      if time.0 < self.start.0 { return; }
+
+      let block = ((time.0 - self.start.0) / self.block.0).floor() as usize;
+      if self.impulses.len() <= block {
+        self.impulses.resize(block + 1, [0.; 4]);
+      }
+      let (side, impulse) = self.impulse(t, prev, next);
+      self.impulses[block][side as usize] += impulse;
+    }
+  }
+
+  /// Reports the pressure on each reflecting side, and on all of them
+  /// together, from the blocks completed by `now`.
+  pub fn report(&self, now: Time) -> Vec<(String, Estimate)> {
+    let complete = ((now.0 - self.start.0) / self.block.0).max(0.).floor() as usize;
+    let impulses = |block: usize| self.impulses.get(block).cloned().unwrap_or([0.; 4]);
+    let walls: Vec<&(Side, &str)> = SIDES.iter()
+      .filter(|&&(side, _)| self.bounds.boundary(side) == Boundary::Reflecting)
+      .collect();
+
+    let mut report: Vec<(String, Estimate)> = walls.iter()
+      .map(|&&(side, name)| {
+        let blocks: Vec<CustomFloat> = (0..complete)
+          .map(|b| impulses(b)[side as usize] / (self.block.0 * self.length(side)))
+          .collect();
+        (format!("wall pressure ({})", name), Estimate::from_blocks(&blocks))
+      })
+      .collect();
+
+    if !walls.is_empty() {
+      let length: CustomFloat = walls.iter().map(|&&(side, _)| self.length(side)).sum();
+      let blocks: Vec<CustomFloat> = (0..complete)
+        .map(|b| {
+          let total: CustomFloat = walls.iter().map(|&&(side, _)| impulses(b)[side as usize]).sum();
+          total / (self.block.0 * length)
+        })
+        .collect();
+      report.push(("wall pressure".to_string(), Estimate::from_blocks(&blocks)));
+    }
+    report
+  }
+}
+
+/// Measures the pressure of a bulk system from the virial of the collisions
+/// between its particles, so it needs no walls, and works with any `Space`.
+///
+/// In 2D, PA = NkT + Σ r_ij · Δp_ij / 2τ, summing over the collisions within
+/// a time τ, where r_ij is the separation of the particles and Δp_ij the
+/// impulse between them. Like `WallPressure`, the virial is summed over
+/// consecutive blocks of time from `start`. In 2D, NkT is the total kinetic
+/// energy, which is integrated over each block in the same way, following
+/// the changes made to it by inelastic collisions and removed particles.
+#[derive(Debug, Clone)]
+pub struct VirialPressure {
+  area: CustomFloat,
+  start: Time,
+  block: Time,
+  // the total kinetic energy since the time of the last change to it
+  kinetic: CustomFloat,
+  changed: Time,
+  // virial of the collisions, and integral of the kinetic energy, in each block
+  virials: Vec<CustomFloat>,
+  kinetics: Vec<CustomFloat>,
+}
+
+impl VirialPressure {
+  /// # Panics
+  /// - if `block` is not positive
+  /// - if `space` has gravity, under which the kinetic energy changes
+  ///   between collisions and the pressure is not uniform
+  pub fn new<S: Space>(space: &S, area: CustomFloat, start: Time, block: Time) -> VirialPressure {
+    assert!(block.0 > 0., "blocks must have a positive duration: {:?}", block);
+    assert!(*space.gravity() == Vector((0., 0.)), "virial pressure is undefined under gravity");
+    VirialPressure {
+      area,
+      start,
+      block,
+      kinetic: space.particles().map(kinetic_energy).sum(),
+      changed: start,
+      virials: Vec::new(),
+      kinetics: Vec::new(),
+    }
+  }
+
+  /// Adds the integral of the current kinetic energy from the time of its
+  /// last change up to `time` to the blocks it spans.
+  fn integrate(&mut self, time: Time) {
+    while self.changed.0 < time.0 {
+      let block = ((self.changed.0 - self.start.0) / self.block.0).floor() as usize;
+      let end = (self.start.0 + (block + 1) as CustomFloat * self.block.0).min(time.0);
+      if self.kinetics.len() <= block {
+        self.kinetics.resize(block + 1, 0.);
+      }
+      self.kinetics[block] += self.kinetic * (end - self.changed.0);
+      self.changed = Time(end);
+    }
+  }
+
+  /// Computes r_ij · Δp_ij for a collision between two particles.
+  ///
+  /// The impulse is along the separation, which at contact is the sum of
+  /// the radii, so the positions are not needed. This avoids finding the
+  /// separation across periodic boundaries. The impulse is found from the
+  /// change in relative velocity, on which uniform acceleration has no effect.
+  fn virial(prev1: &Particle, prev2: &Particle, next1: &Particle, next2: &Particle) -> CustomFloat {
+    let dv = &(&next1.v - &prev1.v) - &(&next2.v - &prev2.v);
+    let reduced_mass = prev1.m * prev2.m / (prev1.m + prev2.m);
+    (prev1.r + prev2.r) * reduced_mass * dv.norm()
+  }
+
+  /// Records the virial of a collision between particles, and the change
+  /// in kinetic energy made by any collision, occurring at the absolute
+  /// time `time`.
+  pub fn record(&mut self, time: Time, collision: &Collision) {
+    // without gravity, the particles move at the velocities they had
+    // before the collision until it occurs
+    let change = match *collision {
+      Collision::Bounce { ref prev1, ref prev2, ref next1, ref next2, .. } =>
+        kinetic_energy(next1) + kinetic_energy(next2) - kinetic_energy(prev1) - kinetic_energy(prev2),
+      Collision::Wall { ref prev, ref next, .. } => kinetic_energy(next) - kinetic_energy(prev),
+      Collision::Exit { ref prev, .. } => -kinetic_energy(prev),
+      Collision::Wrap { .. } |
+      Collision::Cell { .. } |
+      Collision::Free => 0.,
+    };
+    if change != 0. {
+      self.integrate(time);
+      self.kinetic += change;
+    }
+
+    if let Collision::Bounce { ref prev1, ref prev2, ref next1, ref next2, .. } = *collision {
+      if time.0 < self.start.0 { return; }
+
+      let block = ((time.0 - self.start.0) / self.block.0).floor() as usize;
+      if self.virials.len() <= block {
+        self.virials.resize(block + 1, 0.);
+      }
+      self.virials[block] += VirialPressure::virial(prev1, prev2, next1, next2);
+    }
+  }
+
+  /// Reports the pressure from the blocks completed by `now`.
+  pub fn report(&self, now: Time) -> Vec<(String, Estimate)> {
+    let complete = ((now.0 - self.start.0) / self.block.0).max(0.).floor() as usize;
+    let mut integrated = self.clone();
+    integrated.integrate(Time(self.start.0 + complete as CustomFloat * self.block.0));
+    let blocks: Vec<CustomFloat> = (0..complete)
+      .map(|b| {
+        let virial = self.virials.get(b).cloned().unwrap_or(0.);
+        let kinetic = integrated.kinetics.get(b).cloned().unwrap_or(0.);
+        (kinetic + virial / 2.) / (self.block.0 * self.area)
+      })
+      .collect();
+    vec![("virial pressure".to_string(), Estimate::from_blocks(&blocks))]
+  }
+}
+
+fn kinetic_energy(p: &Particle) -> CustomFloat {
+  0.5 * p.m * p.v.norm2()
+}
+
+/// The pressure of `n` particles at temperature `kt`
+/// in the given area, according to the ideal gas law.
+pub fn ideal_gas_pressure(n: usize, kt: CustomFloat, area: CustomFloat) -> CustomFloat {
+  n as CustomFloat * kt / area
+}
+
+/// The pressure of `n` hard disks at temperature `kt` in the given area,
+/// covering `packing_fraction` of it, according to Henderson's equation of
+/// state: PA / NkT = (1 + η²/8) / (1 - η)².
+pub fn henderson_pressure(n: usize, kt: CustomFloat, area: CustomFloat,
+                          packing_fraction: CustomFloat) -> CustomFloat {
+  let eta = packing_fraction;
+  ideal_gas_pressure(n, kt, area) * (1. + eta * eta / 8.) / ((1. - eta) * (1. - eta))
+}
+
+/// The pair distribution function of hard disks at contact, g(σ), for the
+/// given packing fraction, consistent with Henderson's equation of state:
+/// g(σ) = (1 - 7η/16) / (1 - η)².
+pub fn henderson_contact_value(packing_fraction: CustomFloat) -> CustomFloat {
+  let eta = packing_fraction;
+  (1. - 7. * eta / 16.) / ((1. - eta) * (1. - eta))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{Restitution, Simulation, SpaceTime};
+  use super::super::fixtures::{bouncing_between_walls, head_on_across_periodic_boundaries};
+
+  #[test]
+  fn single_particle_pushes_on_walls() {
+    let space = bouncing_between_walls();
+    let mut pressure = WallPressure::new(&space, Time(0.), Time(16.));
+    Simulation::new(SpaceTime::new(space, Time(0.)), Time(1.))
+      .with_observer(&mut pressure)
+      .run(80);
+
+    let report = pressure.report(Time(80.));
+    let right = report.iter().find(|(name, _)| name == "wall pressure (right)").unwrap().1;
+    // an impulse of 4 every 16 time units, on a wall of length 10
+    assert!((right.value - 0.025).abs() < 1e-12, "{:?}", report);
+    assert!(right.error < 1e-12, "{:?}", report);
+  }
+
+  #[test]
+  fn virial_counts_head_on_collisions_across_periodic_boundaries() {
+    let space = head_on_across_periodic_boundaries();
+    let mut pressure = VirialPressure::new(&space, 100., Time(0.), Time(4.));
+    Simulation::new(SpaceTime::new(space, Time(0.)), Time(1.))
+      .with_observer(&mut pressure)
+      .run(20);
+
+    let report = pressure.report(Time(20.));
+    let virial = report[0].1;
+    // NkT / A = 0.01, and each collision has a virial of 2
+    assert!((virial.value - 0.0125).abs() < 1e-12, "{:?}", report);
+    assert!(virial.error < 1e-12, "{:?}", report);
+  }
+
+  #[test]
+  fn virial_follows_the_energy_lost_in_inelastic_collisions() {
+    // each collision keeps a quarter of the kinetic energy, so the particles
+    // separate at half the speed, and meet again across the seam at t = 8.5
+    let space = head_on_across_periodic_boundaries()
+      .with_restitution(Restitution::new(0.5, 0.), Restitution::elastic());
+    let mut pressure = VirialPressure::new(&space, 100., Time(0.), Time(4.));
+    Simulation::new(SpaceTime::new(space, Time(0.)), Time(1.))
+      .with_observer(&mut pressure)
+      .run(16);
+
+    let report = pressure.report(Time(16.));
+    let virial = report[0].1;
+    // the kinetic energy integrated over each block, and the virial of the
+    // collisions within it
+    let blocks = [(1. * 0.5 + 0.25 * 3.5, 1.5), (0.25 * 4., 0.),
+                  (0.25 * 0.5 + 0.0625 * 3.5, 0.75), (0.0625 * 4., 0.)];
+    let expected = blocks.iter().map(|&(k, v)| (k + v / 2.) / 400.).sum::<CustomFloat>() / 4.;
+    assert!((virial.value - expected).abs() < 1e-12, "{:?} did not equal {:?}", virial.value, expected);
+  }
+}
@@ -0,0 +1,146 @@
+use super::{Bounds, CustomFloat, Histogram, Space, SpaceBox, SpaceTime, Vector};
+use super::custom_float::consts::PI;
+
+/// The radial distribution function g(r), accumulated from the distances
+/// between pairs of particles in sampled states of a `SpaceBox`.
+///
+/// g(r) is normalized by the distribution of distances between uniformly
+/// placed points in the same box, so it tends to 1 for an ideal gas. Along
+/// bounded axes, this accounts for pairs lost past the walls, which keep the
+/// centres of particles their radius away. With particles of different
+/// radii, the mean radius is taken. Along periodic axes, distances are
+/// measured to the nearest image.
+#[derive(Debug, Clone)]
+pub struct RadialDistribution {
+  bounds: Bounds,
+  distances: Histogram,
+  // the number of pairs expected in each bin, summed over the samples
+  expected: Vec<CustomFloat>,
+}
+
+impl RadialDistribution {
+  /// Bins distances over `[0, r_max)` into `bins` bins.
+  ///
+  /// # Panics
+  /// - if `r_max` is wider than a bounded axis of `bounds`,
+  ///   or half as wide as a periodic axis
+  pub fn new(bounds: &Bounds, r_max: CustomFloat, bins: usize) -> RadialDistribution {
+    let Vector((wx, wy)) = bounds.top_right() - bounds.bottom_left();
+    let (px, py) = bounds.periodic();
+    let limit = |w: CustomFloat, periodic: bool| if periodic { w / 2. } else { w };
+    assert!(
+      r_max <= limit(wx, px) && r_max <= limit(wy, py),
+      "r_max {} is too wide for bounds {:?}", r_max, bounds
+    );
+    RadialDistribution {
+      bounds: bounds.clone(),
+      distances: Histogram::new(0., r_max, bins),
+      expected: vec![0.; bins],
+    }
+  }
+
+  /// Adds the distance between every pair of particles in the state.
+  pub fn sample(&mut self, s: &SpaceTime<SpaceBox>) {
+    for (p1, p2) in s.space.space_vec().particle_pairs() {
+      self.distances.add(self.bounds.separation(&p1.x, &p2.x).norm());
+    }
+    let n = s.space.particles().len();
+    if n < 2 { return; }
+    let pairs = (n * (n - 1) / 2) as CustomFloat;
+    let r = s.space.particles().map(|p| p.r).sum::<CustomFloat>() / n as CustomFloat;
+    let added: Vec<CustomFloat> = (0..self.expected.len())
+      .map(|i| {
+        let (lo, hi) = self.distances.edges(i);
+        pairs * (self.uniform_cdf(hi, r) - self.uniform_cdf(lo, r))
+      })
+      .collect();
+    for (expected, added) in self.expected.iter_mut().zip(added) {
+      *expected += added;
+    }
+  }
+
+  pub fn distances(&self) -> &Histogram {
+    &self.distances
+  }
+
+  /// Returns g(r) at the centre of each bin.
+  pub fn g(&self) -> Vec<(CustomFloat, CustomFloat)> {
+    self.distances.counts().iter().zip(&self.expected).enumerate()
+      .map(|(i, (&count, &expected))| {
+        let (lo, hi) = self.distances.edges(i);
+        let g = if expected > 0. { count as CustomFloat / expected } else { 0. };
+        ((lo + hi) / 2., g)
+      })
+      .collect()
+  }
+
+  /// The probability that the centres of two particles of the given
+  /// `radius`, placed uniformly in the bounds, lie within `r` of each other.
+  ///
+  /// This integrates, over the circle of radius `r`, the probability density
+  /// of a displacement (dx, dy), which is the fraction of the region open to
+  /// the centres in which both fit: (wx - |dx|)(wy - |dy|) / (wx wy)². Along
+  /// bounded axes, the width w is narrowed by the radius on either side, and
+  /// along periodic axes, the |dx| and |dy| terms drop out.
+  fn uniform_cdf(&self, r: CustomFloat, radius: CustomFloat) -> CustomFloat {
+    let Vector((wx, wy)) = self.bounds.top_right() - self.bounds.bottom_left();
+    let (px, py) = self.bounds.periodic();
+    let (bx, by) = (if px { 0. } else { 1. }, if py { 0. } else { 1. });
+    let (wx, wy) = (wx - 2. * radius * bx, wy - 2. * radius * by);
+    let area = wx * wy;
+    (PI * area * r * r - 4. / 3. * r * r * r * (bx * wy + by * wx) + 0.5 * bx * by * r * r * r * r)
+      / (area * area)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{Boundary, Particle, SeededRng, SpaceBox, SpaceTime, Time, Vector};
+
+  fn ideal_gas_g(boundary: Boundary) -> Vec<(CustomFloat, CustomFloat)> {
+    let min = Particle::new(0, Vector((-5., -5.)), Vector((-1., -1.)), 1e-6, 1.);
+    let max = Particle::new(0, Vector((5., 5.)), Vector((1., 1.)), 1e-6, 1.);
+    let mut rng = SeededRng::new(123);
+    let bounds = SpaceBox::new_random(&mut rng, 1, min.clone(), max.clone()).unwrap()
+      .with_boundary(boundary).bounds().clone();
+
+    let mut rdf = RadialDistribution::new(&bounds, 5., 10);
+    for _ in 0..20 {
+      let space = SpaceBox::new_random(&mut rng, 100, min.clone(), max.clone()).unwrap()
+        .with_boundary(boundary);
+      rdf.sample(&SpaceTime::new(space, Time(0.)));
+    }
+    rdf.g()
+  }
+
+  #[test]
+  fn ideal_gas_is_uniform_in_a_box() {
+    let g = ideal_gas_g(Boundary::Reflecting);
+    assert!(g.iter().all(|&(_, g)| (g - 1.).abs() < 0.1), "{:?}", g);
+  }
+
+  #[test]
+  fn dilute_gas_of_wide_particles_is_uniform_in_a_box() {
+    // a tenth of the box is lost to the walls
+    let min = Particle::new(0, Vector((-4.5, -4.5)), Vector((-1., -1.)), 0.5, 1.);
+    let max = Particle::new(0, Vector((4.5, 4.5)), Vector((1., 1.)), 0.5, 1.);
+    let mut rng = SeededRng::new(321);
+    let mut rdf = None;
+    for _ in 0..500 {
+      let space = SpaceBox::new_random(&mut rng, 10, min.clone(), max.clone()).unwrap();
+      rdf.get_or_insert_with(|| RadialDistribution::new(space.bounds(), 5., 10))
+        .sample(&SpaceTime::new(space, Time(0.)));
+    }
+
+    // beyond contact, where excluded volume has little effect
+    let g = rdf.unwrap().g();
+    assert!(g.iter().filter(|&&(r, _)| r > 1.5).all(|&(_, g)| (g - 1.).abs() < 0.1), "{:?}", g);
+  }
+
+  #[test]
+  fn ideal_gas_is_uniform_under_periodic_boundaries() {
+    let g = ideal_gas_g(Boundary::Periodic);
+    assert!(g.iter().all(|&(_, g)| (g - 1.).abs() < 0.1), "{:?}", g);
+  }
+}
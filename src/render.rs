@@ -0,0 +1,245 @@
+use std::io::{self, Write};
+use super::{CustomFloat, Particle, Space, SpaceBox, SpaceTime, Vector};
+
+/// How a `Renderer` chooses the colour of each particle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Colouring {
+  /// Spreads the ids of the particles over the colour scale, so that
+  /// each particle keeps a distinct colour as it moves.
+  Id,
+  /// Colours particles from blue when at rest to red at the given speed
+  /// and above.
+  Speed(CustomFloat),
+}
+
+impl Colouring {
+  /// Returns the index into `palette` of the colour of `p`.
+  pub fn index(&self, p: &Particle) -> u8 {
+    match *self {
+      // a stride coprime to the 255 colours visits all of them
+      Colouring::Id => 1 + (p.id.wrapping_mul(98) % 255) as u8,
+      Colouring::Speed(max) => 1 + (254. * (p.v.norm() / max).min(1.)).round() as u8,
+    }
+  }
+}
+
+/// The colours of the pixels in a `Frame`: white for the background,
+/// followed by a scale from blue to red, which fits a GIF's colour table.
+pub fn palette() -> Vec<[u8; 3]> {
+  let scale = (0..255).map(|i| {
+    // hue from 240 degrees down to 0
+    let h = 4. * (1. - i as CustomFloat / 254.);
+    let f = h - h.floor();
+    let (up, down) = ((255. * f).round() as u8, (255. * (1. - f)).round() as u8);
+    match h.floor() as usize {
+      0 => [255, up, 0],
+      1 => [down, 255, 0],
+      2 => [0, 255, up],
+      3 => [0, down, 255],
+      _ => [0, 0, 255],
+    }
+  });
+  Some([255, 255, 255]).into_iter().chain(scale).collect()
+}
+
+/// An image of a state, with each pixel as an index into `palette`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+  width: usize,
+  height: usize,
+  pixels: Vec<u8>,
+  // the seed of the random initial state, recorded in the image files
+  seed: Option<usize>,
+}
+
+impl Frame {
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  pub fn height(&self) -> usize {
+    self.height
+  }
+
+  /// Returns the palette index of each pixel, row by row from the top left.
+  pub fn pixels(&self) -> &[u8] {
+    &self.pixels
+  }
+
+  fn rgb(&self) -> Vec<u8> {
+    let palette = palette();
+    self.pixels.iter().flat_map(|&i| palette[i as usize].to_vec()).collect()
+  }
+
+  /// Writes the frame as a binary PPM image.
+  pub fn write_ppm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+    writeln!(out, "P6")?;
+    if let Some(seed) = self.seed {
+      writeln!(out, "# seed {}", seed)?;
+    }
+    write!(out, "{} {}\n255\n", self.width, self.height)?;
+    out.write_all(&self.rgb())
+  }
+
+  /// Writes the frame as a PNG image. The image data is stored without
+  /// compression, which needs no more than a checksum to encode.
+  pub fn write_png<W: Write>(&self, out: &mut W) -> io::Result<()> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(self.width as u32).to_be_bytes());
+    header.extend_from_slice(&(self.height as u32).to_be_bytes());
+    // 8 bit RGB, with no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    // each row is preceded by the filter type, which is none
+    let rgb = self.rgb();
+    let mut raw = Vec::with_capacity(rgb.len() + self.height);
+    for row in rgb.chunks(3 * self.width) {
+      raw.push(0);
+      raw.extend_from_slice(row);
+    }
+
+    out.write_all(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'])?;
+    write_chunk(out, b"IHDR", &header)?;
+    if let Some(seed) = self.seed {
+      write_chunk(out, b"tEXt", format!("Comment\0seed {}", seed).as_bytes())?;
+    }
+    write_chunk(out, b"IDAT", &zlib_stored(&raw))?;
+    write_chunk(out, b"IEND", &[])
+  }
+}
+
+/// Draws states as `Frame`s, with every particle as a disk of its exact
+/// radius, scaled to fit the box of the `SpaceBox` into `width` pixels.
+/// Particles crossing a periodic side are also drawn across the opposite
+/// side.
+#[derive(Debug, Clone)]
+pub struct Renderer {
+  width: usize,
+  colouring: Colouring,
+  seed: Option<usize>,
+}
+
+impl Renderer {
+  pub fn new(width: usize) -> Renderer {
+    assert!(width > 0, "frames must be at least a pixel wide");
+    Renderer { width, colouring: Colouring::Id, seed: None }
+  }
+
+  pub fn with_colouring(self, colouring: Colouring) -> Renderer {
+    Renderer { colouring, .. self }
+  }
+
+  /// Records the seed of the random initial state in a comment in the PPM
+  /// and PNG images of each frame.
+  pub fn with_seed(self, seed: usize) -> Renderer {
+    Renderer { seed: Some(seed), .. self }
+  }
+
+  pub fn render(&self, s: &SpaceTime<SpaceBox>) -> Frame {
+    let bounds = s.space.bounds();
+    let Vector((ox, oy)) = *bounds.bottom_left();
+    let Vector((wx, wy)) = bounds.top_right() - bounds.bottom_left();
+    let scale = self.width as CustomFloat / wx;
+    let height = ((wy * scale).round() as usize).max(1);
+    let mut pixels = vec![0; self.width * height];
+
+    // pixels are numbered from the top left, and sampled at their centres
+    let column = |x: CustomFloat| (x - ox) * scale - 0.5;
+    let row = |y: CustomFloat| (oy + wy - y) * scale - 0.5;
+    let clamp = |i: CustomFloat, n: usize| i.max(0.).min(n as CustomFloat) as usize;
+
+    for p in s.space.particles() {
+      let colour = self.colouring.index(p);
+      let r = p.r * scale;
+      for offset in bounds.images() {
+        let Vector((x, y)) = &p.x + &offset;
+        let (cx, cy) = (column(x), row(y));
+        for j in clamp((cy - r).ceil(), height)..clamp((cy + r).floor() + 1., height) {
+          for i in clamp((cx - r).ceil(), self.width)..clamp((cx + r).floor() + 1., self.width) {
+            let (dx, dy) = (i as CustomFloat - cx, j as CustomFloat - cy);
+            if dx * dx + dy * dy <= r * r {
+              pixels[i + j * self.width] = colour;
+            }
+          }
+        }
+      }
+    }
+
+    Frame { width: self.width, height, pixels, seed: self.seed }
+  }
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8], data: &[u8]) -> io::Result<()> {
+  out.write_all(&(data.len() as u32).to_be_bytes())?;
+  out.write_all(kind)?;
+  out.write_all(data)?;
+  let crc = !kind.iter().chain(data).fold(!0, |crc, &b| crc32_byte(crc, b));
+  out.write_all(&crc.to_be_bytes())
+}
+
+fn crc32_byte(crc: u32, b: u8) -> u32 {
+  (0..8).fold(crc ^ b as u32, |c, _| if c & 1 == 1 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 })
+}
+
+/// Wraps `data` in a zlib stream of uncompressed blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+  let mut out = vec![0x78, 0x01];
+  let blocks: Vec<&[u8]> = data.chunks(0xffff).collect();
+  for (i, block) in blocks.iter().enumerate() {
+    out.push(if i + 1 == blocks.len() { 1 } else { 0 });
+    let len = block.len() as u16;
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(&(!len).to_le_bytes());
+    out.extend_from_slice(block);
+  }
+  if blocks.is_empty() {
+    out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+  }
+
+  let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &x| {
+    let a = (a + x as u32) % 65521;
+    (a, (b + a) % 65521)
+  });
+  out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{Boundary, Particle, SpaceBox, SpaceTime, Time, Vector};
+
+  #[test]
+  fn disks_are_drawn_at_their_exact_radius() {
+    // at 4 pixels per unit, a disk of radius 3 pixels,
+    // and one of radius 2 wrapped across the left side
+    let p1 = Particle::new(0, Vector((2., 1.25)), Vector((0., 0.)), 0.75, 1.);
+    let p2 = Particle::new(1, Vector((0.25, 0.5)), Vector((2., 0.)), 0.5, 1.);
+    let space = SpaceBox::new(vec![p1, p2], Vector((0., 0.)), Vector((3., 2.)))
+      .with_boundary(Boundary::Periodic);
+    let frame = Renderer::new(12).with_colouring(Colouring::Speed(2.))
+      .render(&SpaceTime::new(space, Time(0.)));
+
+    let text: Vec<String> = frame.pixels().chunks(frame.width())
+      .map(|row| row.iter().map(|&c| match c { 0 => '.', 1 => 'o', _ => '#' }).collect())
+      .collect();
+    let expected = vec![
+      "......oooo..",
+      ".....oooooo.",
+      ".....oooooo.",
+      ".....oooooo.",
+      "##...oooooo.",
+      "###...oooo.#",
+      "###........#",
+      "##..........",
+    ];
+    assert!(text == expected, "{:?} did not equal {:?}", text, expected);
+  }
+
+  #[test]
+  fn png_checksums_match_known_values() {
+    assert!(!b"IEND".iter().fold(!0, |crc, &b| crc32_byte(crc, b)) == 0xae42_6082);
+    let stored = zlib_stored(b"Wikipedia");
+    assert!(stored[stored.len() - 4..] == [0x11, 0xe6, 0x03, 0x98], "{:?}", stored);
+  }
+}
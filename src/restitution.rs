@@ -0,0 +1,39 @@
+use super::CustomFloat;
+
+/// The coefficient of restitution of a collision: the fraction of the
+/// relative velocity normal to the point of contact which is retained.
+///
+/// Inelastic collisions may collapse, with infinitely many collisions in a
+/// finite time, as the relative velocities of clustered particles vanish.
+/// To prevent this, impacts with a normal speed below `cutoff` are elastic.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Restitution {
+  pub e: CustomFloat,
+  pub cutoff: CustomFloat,
+}
+
+impl Restitution {
+  /// # Panics
+  /// - if `e` is not within (0, 1]
+  /// - if `cutoff` is negative
+  pub fn new(e: CustomFloat, cutoff: CustomFloat) -> Restitution {
+    assert!(0. < e && e <= 1., "coefficient of restitution must be within (0, 1]: {}", e);
+    assert!(cutoff >= 0., "restitution cutoff must not be negative: {}", cutoff);
+    Restitution { e, cutoff }
+  }
+
+  pub fn elastic() -> Restitution {
+    Restitution { e: 1., cutoff: 0. }
+  }
+
+  /// Returns the coefficient for an impact with the given normal speed.
+  pub fn at(&self, normal_speed: CustomFloat) -> CustomFloat {
+    if normal_speed.abs() < self.cutoff { 1. } else { self.e }
+  }
+}
+
+impl Default for Restitution {
+  fn default() -> Restitution {
+    Restitution::elastic()
+  }
+}
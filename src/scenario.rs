@@ -0,0 +1,662 @@
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+use rand::Rng;
+use super::packing;
+use super::{Boundary, BoundedRand, Bounds, CustomFloat, Lattice, Particle, Restitution, SeededRng,
+  SpaceBox, SpaceTime, Time, Vector, Velocities};
+
+/// A simulation described as data, parsed from a text file.
+///
+/// Each line holds a setting, followed by its values, which are either
+/// given in order or as `name=value`. Ranges are written as `min,max`, or as
+/// a single value when they hold only it, and vectors as `x,y`. Everything
+/// after a `#` is a comment. For example:
+///
+/// ```text
+/// # a gas of two species in a channel
+/// box -10,-5 10,5
+/// boundary left=periodic right=periodic
+/// restitution particles=0.9 walls=1 cutoff=0.01
+/// gravity 0,-0.5
+/// cells
+/// step 0.1
+/// until 20
+/// seed 7
+///
+/// species count=20 radius=0.3,0.5 mass=1 velocity=-1,1
+/// species count=5,10 radius=1 mass=4 velocity=-0.5,0.5
+/// particle x=0,0 v=1,0 r=0.5 m=1
+/// init random
+///
+/// observe conservation
+/// observe wall-pressure block=1
+/// output gif tmp/channel.gif
+/// output xyz tmp/channel.xyz
+/// ```
+///
+/// Explicit particles are placed first, then the particles of each species
+/// in turn. A species may give the `fraction` of the box its particles
+/// cover, such as `fraction=0.6`, rather than their `count`, and a
+/// `temperature` to draw their velocities from rather than a `velocity`
+/// range. Particles are placed by `init random`, `init adsorption`, or on a
+/// lattice with `init square spacing=1.2` or `init triangular spacing=1.2`.
+///
+/// Every setting other than `box` has a default: reflecting sides, elastic
+/// collisions, no gravity or cells, a step of 0.1 for 100 frames, random
+/// initialization, and no observers or outputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+  pub bottom_left: Vector,
+  pub top_right: Vector,
+  /// The boundaries of the left, right, bottom and top sides.
+  pub sides: [Boundary; 4],
+  /// The restitution of collisions between particles, and with walls.
+  pub restitution: (Restitution, Restitution),
+  pub gravity: Vector,
+  pub cells: bool,
+  pub step: Time,
+  pub length: Length,
+  /// The seed of the random initial state, or None to draw one from the OS.
+  pub seed: Option<usize>,
+  pub species: Vec<Species>,
+  pub particles: Vec<Particle>,
+  pub init: Init,
+  pub observers: Vec<Observation>,
+  pub outputs: Vec<(Format, String)>,
+}
+
+/// A kind of particle, of which an `amount` are drawn, each with a radius
+/// and mass within the ranges, and a velocity drawn from `velocity`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Species {
+  pub amount: Amount,
+  pub radius: (CustomFloat, CustomFloat),
+  pub mass: (CustomFloat, CustomFloat),
+  pub velocity: Velocities,
+}
+
+/// How many particles of a species to draw.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Amount {
+  /// A random number within the range.
+  Count(usize, usize),
+  /// As many as cover this fraction of the area of the box.
+  Fraction(CustomFloat),
+}
+
+/// How the particles of each species are placed in the box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Init {
+  /// At uniformly random positions which do not overlap, or on a melted
+  /// lattice when they are too dense for that.
+  Random,
+  /// At random sites of the lattice, spaced this far apart, which must have
+  /// a site for every particle.
+  Lattice(Lattice, CustomFloat),
+  /// By random sequential adsorption, at uniformly random positions which
+  /// do not overlap, failing once no position can be found.
+  Adsorption,
+}
+
+/// How long to run a simulation for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+  /// A number of states to sample.
+  Frames(usize),
+  /// Sample states up to this time.
+  Until(Time),
+}
+
+impl Length {
+  /// Returns the number of states to sample, a `step` apart from time zero.
+  pub fn frames(&self, step: Time) -> usize {
+    match *self {
+      Length::Frames(n) => n,
+      // allowing for rounding, so that a sample at exactly `t` is included
+      Length::Until(t) => (t.0 / step.0 + 1e-9).floor() as usize + 1,
+    }
+  }
+}
+
+/// A measurement to make during a simulation, with the parameters of the
+/// observer which makes it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Observation {
+  /// `ConservationMonitor`
+  Conservation,
+  /// `WallPressure`
+  WallPressure { block: Time },
+  /// `VirialPressure`
+  VirialPressure { block: Time },
+  /// `VelocityDistribution`
+  Velocity { max_speed: CustomFloat, bins: usize },
+  /// `RadialDistribution`
+  Radial { r_max: CustomFloat, bins: usize },
+  /// `MeanSquaredDisplacement`
+  Msd { origin_every: usize, max_lag: usize },
+  /// `VelocityAutocorrelation`
+  Vacf { lag_step: Time, max_lag: usize, origin_every: Time },
+  /// `FreePaths`
+  FreePaths { max_time: CustomFloat, max_path: CustomFloat, bins: usize },
+}
+
+/// A format in which to write the states sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+  /// An animated GIF, drawn by a `Renderer`.
+  Gif,
+  /// An animated SVG, drawn by an `SvgWriter`.
+  Svg,
+  /// An extended XYZ trajectory.
+  Xyz,
+  /// A PNG image of each state.
+  Png,
+  /// A PPM image of each state.
+  Ppm,
+}
+
+impl FromStr for Format {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Format, String> {
+    match s {
+      "gif" => Ok(Format::Gif),
+      "svg" => Ok(Format::Svg),
+      "xyz" => Ok(Format::Xyz),
+      "png" => Ok(Format::Png),
+      "ppm" => Ok(Format::Ppm),
+      _ => Err(format!("unknown format `{}`", s)),
+    }
+  }
+}
+
+/// Why a scenario could not be parsed or built. `line` is the line of the
+/// file at fault, or zero if it is not any single line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScenarioError {
+  pub line: usize,
+  pub message: String,
+}
+
+impl fmt::Display for ScenarioError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.line > 0 {
+      write!(f, "line {}: {}", self.line, self.message)
+    } else {
+      write!(f, "{}", self.message)
+    }
+  }
+}
+
+impl error::Error for ScenarioError {}
+
+impl Scenario {
+  /// A scenario in the given box, with every other setting at its default.
+  pub fn new(bottom_left: Vector, top_right: Vector) -> Scenario {
+    Scenario {
+      bottom_left,
+      top_right,
+      sides: [Boundary::Reflecting; 4],
+      restitution: (Restitution::elastic(), Restitution::elastic()),
+      gravity: Vector((0., 0.)),
+      cells: false,
+      step: Time(0.1),
+      length: Length::Frames(100),
+      seed: None,
+      species: Vec::new(),
+      particles: Vec::new(),
+      init: Init::Random,
+      observers: Vec::new(),
+      outputs: Vec::new(),
+    }
+  }
+
+  pub fn bounds(&self) -> Bounds {
+    let [left, right, bottom, top] = self.sides;
+    Bounds::new(self.top_right, self.bottom_left)
+      .with_sides(left, right, bottom, top)
+      .with_restitution(self.restitution.1)
+  }
+
+  /// Places the particles, drawing from `rng`, and returns the initial
+  /// state at time zero.
+  pub fn build(&self, rng: &mut SeededRng) -> Result<SpaceTime<SpaceBox>, ScenarioError> {
+    let bounds = self.bounds();
+    let error = |message: String| ScenarioError { line: 0, message };
+    let overlaps = |ps: &[Particle], p: &Particle|
+      ps.iter().any(|q| bounds.separation(&p.x, &q.x).norm() < p.r + q.r - 1e-5);
+
+    let mut particles: Vec<Particle> = Vec::new();
+    for p in &self.particles {
+      if !bounds.within(p) {
+        return Err(error(format!("particle at {:?} is outside the box", p.x)));
+      } else if overlaps(&particles, p) {
+        return Err(error(format!("particle at {:?} overlaps another", p.x)));
+      }
+      particles.push(Particle { id: particles.len(), .. p.clone() });
+    }
+
+    let mut drawn = Vec::new();
+    for species in &self.species {
+      let Vector((wx, wy)) = &self.top_right - &self.bottom_left;
+      if 2. * species.radius.1 >= wx.min(wy) {
+        return Err(error(format!("particles of radius {} do not fit in the box", species.radius.1)));
+      }
+      // positions are drawn when the particles are placed, and velocities
+      // once all of the species has been drawn
+      let min = Particle::new(0, self.bottom_left, Vector((0., 0.)), species.radius.0, species.mass.0);
+      let max = Particle { r: species.radius.1, m: species.mass.1, .. min.clone() };
+      let mut batch = match species.amount {
+        Amount::Count(lo, hi) => (0..rng.gen_range(lo, hi + 1))
+          .map(|_| BoundedRand::rand(rng, &min, &max))
+          .collect(),
+        Amount::Fraction(fraction) => packing::draw_covering(rng, &min, &max, fraction * bounds.area()),
+      };
+      species.velocity.assign(rng, &mut batch);
+      drawn.extend(batch);
+    }
+    let placed = match self.init {
+      Init::Random => packing::place(rng, &bounds, &particles, drawn),
+      Init::Lattice(lattice, spacing) =>
+        packing::place_on_lattice(rng, &bounds, &particles, drawn, lattice, spacing),
+      Init::Adsorption => packing::place_randomly(rng, &bounds, &particles, drawn),
+    }.map_err(|e| error(e.to_string()))?;
+    for p in placed {
+      particles.push(Particle { id: particles.len(), .. p });
+    }
+
+    let [left, right, bottom, top] = self.sides;
+    let mut space = SpaceBox::new(particles, self.bottom_left, self.top_right)
+      .with_sides(left, right, bottom, top)
+      .with_restitution(self.restitution.0, self.restitution.1)
+      .with_gravity(self.gravity);
+    if self.cells {
+      space = space.with_cells();
+    }
+    Ok(SpaceTime::new(space, Time(0.)))
+  }
+}
+
+impl FromStr for Scenario {
+  type Err = ScenarioError;
+
+  fn from_str(text: &str) -> Result<Scenario, ScenarioError> {
+    let mut scenario = None;
+    for (i, line) in text.lines().enumerate() {
+      let line = line.split('#').next().unwrap_or("");
+      let mut words = line.split_whitespace();
+      let setting = match words.next() {
+        Some(setting) => setting,
+        None => continue,
+      };
+      let mut values = Values::new(words)
+        .map_err(|message| ScenarioError { line: i + 1, message })?;
+
+      parse_setting(&mut scenario, setting, &mut values)
+        .and_then(|()| values.finish())
+        .map_err(|message| ScenarioError { line: i + 1, message })?;
+    }
+    scenario.ok_or_else(|| ScenarioError { line: 0, message: "the box is not given".to_string() })
+  }
+}
+
+fn parse_setting(scenario: &mut Option<Scenario>, setting: &str, values: &mut Values)
+                 -> Result<(), String> {
+  if setting == "box" {
+    if scenario.is_some() {
+      return Err("the box is given twice".to_string());
+    }
+    let (bottom_left, top_right) = (values.vector_at(0)?, values.vector_at(1)?);
+    let Vector((x0, y0)) = bottom_left;
+    let Vector((x1, y1)) = top_right;
+    if !(x0 < x1 && y0 < y1) {
+      return Err("the box must have its bottom left corner first".to_string());
+    }
+    *scenario = Some(Scenario::new(bottom_left, top_right));
+    return Ok(());
+  }
+  let s = scenario.as_mut().ok_or_else(|| "the box must be given first".to_string())?;
+
+  match setting {
+    "boundary" => {
+      let all = values.optional_at(0)?;
+      for (i, side) in ["left", "right", "bottom", "top"].iter().enumerate() {
+        if let Some(boundary) = values.optional(side)?.or(all) {
+          s.sides[i] = boundary;
+        }
+      }
+      if (s.sides[0] == Boundary::Periodic) != (s.sides[1] == Boundary::Periodic) ||
+         (s.sides[2] == Boundary::Periodic) != (s.sides[3] == Boundary::Periodic) {
+        return Err("periodic sides must be opposite each other".to_string());
+      }
+      for observation in &s.observers {
+        if let Observation::Radial { r_max, .. } = *observation {
+          check_radial(s, r_max)?;
+        }
+      }
+    },
+    "restitution" => {
+      let cutoff = values.optional("cutoff")?.unwrap_or(0.);
+      let coefficient = |e: Option<CustomFloat>| match e {
+        Some(e) if !(0. < e && e <= 1. && cutoff >= 0.) =>
+          Err("restitution must be within (0, 1], with a cutoff of at least 0".to_string()),
+        Some(e) => Ok(Restitution::new(e, cutoff)),
+        None => Ok(Restitution::elastic()),
+      };
+      s.restitution = (coefficient(values.optional("particles")?)?, coefficient(values.optional("walls")?)?);
+    },
+    "gravity" => {
+      s.gravity = values.vector_at(0)?;
+      for observation in &s.observers {
+        if let Observation::VirialPressure { .. } = *observation {
+          check_virial(s)?;
+        }
+      }
+    },
+    "cells" => s.cells = true,
+    "step" => {
+      s.step = Time(values.at(0)?);
+      if s.step.0.is_nan() || s.step.0 <= 0. { return Err("the step must be positive".to_string()); }
+    },
+    "frames" => s.length = Length::Frames(values.at(0)?),
+    "until" => s.length = Length::Until(Time(values.at(0)?)),
+    "seed" => s.seed = Some(values.at(0)?),
+    "species" => {
+      let amount = match values.optional("fraction")? {
+        Some(fraction) if !(0. < fraction && fraction < 1.) =>
+          return Err("the fraction must be within (0, 1)".to_string()),
+        Some(fraction) => Amount::Fraction(fraction),
+        None => {
+          let (lo, hi) = values.range("count")?;
+          Amount::Count(lo, hi)
+        },
+      };
+      let species = Species {
+        amount,
+        radius: values.range("radius")?,
+        mass: values.range("mass")?,
+        velocity: match values.optional::<CustomFloat>("temperature")? {
+          Some(kt) if kt.is_nan() || kt < 0. => return Err("the temperature must not be negative".to_string()),
+          Some(kt) => Velocities::Temperature(kt),
+          None => {
+            let (min, max) = values.range("velocity")?;
+            Velocities::Uniform(min, max)
+          },
+        },
+      };
+      if !(species.radius.0 > 0. && species.mass.0 > 0.) {
+        return Err("radii and masses must be positive".to_string());
+      }
+      s.species.push(species);
+    },
+    "particle" => {
+      let p = Particle::new(0, values.vector("x")?, values.vector("v")?, values.get("r")?, values.get("m")?);
+      if !(p.r > 0. && p.m > 0.) {
+        return Err("radii and masses must be positive".to_string());
+      }
+      s.particles.push(p);
+    },
+    "init" => s.init = match values.word_at(0)? {
+      "random" => Init::Random,
+      "adsorption" => Init::Adsorption,
+      other => match other.parse() {
+        Ok(lattice) => {
+          let spacing: CustomFloat = values.get("spacing")?;
+          if spacing.is_nan() || spacing <= 0. { return Err("the spacing must be positive".to_string()); }
+          Init::Lattice(lattice, spacing)
+        },
+        Err(_) => return Err(format!("unknown initialization `{}`", other)),
+      },
+    },
+    "observe" => {
+      let observation = match values.word_at(0)? {
+        "conservation" => Observation::Conservation,
+        "wall-pressure" => Observation::WallPressure { block: Time(positive(values, "block")?) },
+        "virial-pressure" => {
+          check_virial(s)?;
+          Observation::VirialPressure { block: Time(positive(values, "block")?) }
+        },
+        "velocity" => Observation::Velocity {
+          max_speed: positive(values, "max-speed")?,
+          bins: nonzero(values, "bins")?,
+        },
+        "radial" => {
+          let r_max = positive(values, "r-max")?;
+          check_radial(s, r_max)?;
+          Observation::Radial { r_max, bins: nonzero(values, "bins")? }
+        },
+        "msd" => Observation::Msd {
+          origin_every: nonzero(values, "origin-every")?,
+          max_lag: values.get("max-lag")?,
+        },
+        "vacf" => Observation::Vacf {
+          lag_step: Time(positive(values, "lag-step")?),
+          max_lag: values.get("max-lag")?,
+          origin_every: Time(positive(values, "origin-every")?),
+        },
+        "free-paths" => Observation::FreePaths {
+          max_time: positive(values, "max-time")?,
+          max_path: positive(values, "max-path")?,
+          bins: nonzero(values, "bins")?,
+        },
+        other => return Err(format!("unknown observer `{}`", other)),
+      };
+      s.observers.push(observation);
+    },
+    "output" => {
+      let format = values.at(0)?;
+      s.outputs.push((format, values.word_at(1)?.to_string()));
+    },
+    _ => return Err(format!("unknown setting `{}`", setting)),
+  }
+  Ok(())
+}
+
+/// Checks that a radial distribution out to `r_max` fits in the box, as
+/// `RadialDistribution::new` requires.
+fn check_radial(s: &Scenario, r_max: CustomFloat) -> Result<(), String> {
+  let Vector((wx, wy)) = &s.top_right - &s.bottom_left;
+  let limit = |w: CustomFloat, side: Boundary| if side == Boundary::Periodic { w / 2. } else { w };
+  if r_max > limit(wx, s.sides[0]) || r_max > limit(wy, s.sides[2]) {
+    return Err(format!("`r-max` {} is too wide for the box", r_max));
+  }
+  Ok(())
+}
+
+/// Checks that there is no gravity, as `VirialPressure::new` requires.
+fn check_virial(s: &Scenario) -> Result<(), String> {
+  if s.gravity != Vector((0., 0.)) {
+    return Err("virial pressure is undefined under gravity".to_string());
+  }
+  Ok(())
+}
+
+fn positive(values: &mut Values, name: &str) -> Result<CustomFloat, String> {
+  let x: CustomFloat = values.get(name)?;
+  if x.is_nan() || x <= 0. { return Err(format!("`{}` must be positive", name)); }
+  Ok(x)
+}
+
+fn nonzero(values: &mut Values, name: &str) -> Result<usize, String> {
+  match values.get(name)? {
+    0 => Err(format!("`{}` must be at least 1", name)),
+    n => Ok(n),
+  }
+}
+
+/// The values of a setting, which are removed as they are read, so that
+/// any left over can be reported.
+struct Values<'a> {
+  ordered: Vec<Option<&'a str>>,
+  named: BTreeMap<&'a str, &'a str>,
+}
+
+impl<'a> Values<'a> {
+  fn new<I: Iterator<Item=&'a str>>(words: I) -> Result<Values<'a>, String> {
+    let mut values = Values { ordered: Vec::new(), named: BTreeMap::new() };
+    for word in words {
+      let mut parts = word.splitn(2, '=');
+      match (parts.next(), parts.next()) {
+        (Some(name), Some(value)) => {
+          if values.named.insert(name, value).is_some() {
+            return Err(format!("`{}` is given twice", name));
+          }
+        },
+        _ => values.ordered.push(Some(word)),
+      }
+    }
+    Ok(values)
+  }
+
+  fn word_at(&mut self, i: usize) -> Result<&'a str, String> {
+    self.ordered.get_mut(i).and_then(|v| v.take())
+      .ok_or_else(|| format!("a value is missing at position {}", i + 1))
+  }
+
+  fn at<T: FromStr>(&mut self, i: usize) -> Result<T, String> {
+    parse_value(self.word_at(i)?)
+  }
+
+  fn optional_at<T: FromStr>(&mut self, i: usize) -> Result<Option<T>, String> {
+    match self.ordered.get_mut(i).and_then(|v| v.take()) {
+      Some(word) => parse_value(word).map(Some),
+      None => Ok(None),
+    }
+  }
+
+  fn vector_at(&mut self, i: usize) -> Result<Vector, String> {
+    parse_pair(self.word_at(i)?).map(Vector)
+  }
+
+  fn optional<T: FromStr>(&mut self, name: &str) -> Result<Option<T>, String> {
+    match self.named.remove(name) {
+      Some(word) => parse_value(word).map(Some),
+      None => Ok(None),
+    }
+  }
+
+  fn get<T: FromStr>(&mut self, name: &str) -> Result<T, String> {
+    self.optional(name)?.ok_or_else(|| format!("`{}` is missing", name))
+  }
+
+  fn vector(&mut self, name: &str) -> Result<Vector, String> {
+    let word = self.named.remove(name).ok_or_else(|| format!("`{}` is missing", name))?;
+    parse_pair(word).map(Vector)
+  }
+
+  fn range<T: FromStr + PartialOrd + Copy>(&mut self, name: &str) -> Result<(T, T), String> {
+    let word = self.named.remove(name).ok_or_else(|| format!("`{}` is missing", name))?;
+    let range = match word.find(',') {
+      Some(_) => parse_pair(word)?,
+      None => {
+        let value = parse_value(word)?;
+        (value, value)
+      },
+    };
+    if range.0 <= range.1 { Ok(range) } else { Err(format!("the range `{}` is empty", word)) }
+  }
+
+  /// Fails if any values have not been read.
+  fn finish(&self) -> Result<(), String> {
+    match (self.ordered.iter().flat_map(|v| v.iter()).next(), self.named.keys().next()) {
+      (Some(word), _) => Err(format!("unexpected `{}`", word)),
+      (None, Some(name)) => Err(format!("unknown value `{}`", name)),
+      (None, None) => Ok(()),
+    }
+  }
+}
+
+fn parse_value<T: FromStr>(word: &str) -> Result<T, String> {
+  word.parse().map_err(|_| format!("invalid value `{}`", word))
+}
+
+fn parse_pair<T: FromStr>(word: &str) -> Result<(T, T), String> {
+  let mut parts = word.split(',');
+  match (parts.next(), parts.next(), parts.next()) {
+    (Some(a), Some(b), None) => Ok((parse_value(a)?, parse_value(b)?)),
+    _ => Err(format!("expected two values separated by a comma, not `{}`", word)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{temperature, Boundary, Space, Vector};
+
+  #[test]
+  fn scenario_is_parsed_and_built() {
+    let text = "
+      # two species in a channel
+      box -10,-5 10,5
+      boundary left=periodic right=periodic
+      restitution particles=0.9 cutoff=0.01
+      until 2
+      species count=20 radius=0.3,0.5 mass=1 velocity=-1,1
+      species count=3,5 radius=1 mass=4 velocity=0
+      particle x=0,0 v=1,0 r=0.5 m=1
+      observe wall-pressure block=0.5
+      output xyz run.xyz
+    ";
+    let scenario: Scenario = text.parse().unwrap();
+    assert!(scenario.sides == [Boundary::Periodic, Boundary::Periodic, Boundary::Reflecting,
+                               Boundary::Reflecting], "{:?}", scenario.sides);
+    assert!(scenario.restitution == (Restitution::new(0.9, 0.01), Restitution::elastic()),
+      "{:?}", scenario.restitution);
+    assert!(scenario.length.frames(scenario.step) == 21, "{:?}", scenario.length);
+    assert!(scenario.species[1].amount == Amount::Count(3, 5) &&
+            scenario.species[1].velocity == Velocities::Uniform(0., 0.),
+      "{:?}", scenario.species);
+    assert!(scenario.observers == vec![Observation::WallPressure { block: Time(0.5) }],
+      "{:?}", scenario.observers);
+    assert!(scenario.outputs == vec![(Format::Xyz, "run.xyz".to_string())], "{:?}", scenario.outputs);
+
+    let mut rng = SeededRng::new(12);
+    let init = scenario.build(&mut rng).unwrap();
+    let ps: Vec<&Particle> = init.space.particles().collect();
+    assert!(ps.len() >= 24 && ps.len() <= 26, "{}", ps.len());
+    assert!(ps[0].x == Vector((0., 0.)) && ps.iter().enumerate().all(|(i, p)| p.id == i), "{:?}", ps);
+  }
+
+  #[test]
+  fn species_are_placed_on_a_lattice_at_their_temperature() {
+    let text = "
+      box 0,0 10,10
+      species count=50 radius=0.5 mass=2 temperature=1.5
+      init triangular spacing=1.2
+    ";
+    let scenario: Scenario = text.parse().unwrap();
+    assert!(scenario.init == Init::Lattice(Lattice::Triangular, 1.2), "{:?}", scenario.init);
+
+    let init = scenario.build(&mut SeededRng::new(8)).unwrap();
+    let sites = Lattice::Triangular.sites(&scenario.bounds(), 1.2, 0.5);
+    assert!(init.space.particles().all(|p| sites.contains(&p.x)), "{:?}", init);
+    let kt = temperature(&init.space);
+    assert!((kt - 1.5).abs() < 1e-9, "{}", kt);
+  }
+
+  #[test]
+  fn errors_name_the_line() {
+    let cases = [
+      ("box 0,0 1,1\nspecies count=2 radius=0.1 mass=1\n", 2, "`velocity` is missing"),
+      ("box 0,0 1,1\nboundary left=periodic\n", 2, "periodic sides must be opposite each other"),
+      ("box 0,0 1,1\n\nstep 0.1 fast\n", 3, "unexpected `fast`"),
+      ("step 0.1\n", 1, "the box must be given first"),
+      ("box 0,0 1,1\nobserve everything\n", 2, "unknown observer `everything`"),
+      ("box 0,0 1,1\ninit cubic spacing=1\n", 2, "unknown initialization `cubic`"),
+      ("box 0,0 1,1\nobserve wall-pressure block=0\n", 2, "`block` must be positive"),
+      ("box 0,0 10,10\nobserve radial r-max=50 bins=10\n", 2, "`r-max` 50 is too wide for the box"),
+      ("box 0,0 10,10\nobserve radial r-max=8 bins=10\nboundary left=periodic right=periodic\n", 3,
+       "`r-max` 8 is too wide for the box"),
+      ("box 0,0 1,1\nobserve velocity max-speed=2 bins=0\n", 2, "`bins` must be at least 1"),
+      ("box 0,0 1,1\nobserve virial-pressure block=1\ngravity 0,-1\n", 3, "virial pressure is undefined under gravity"),
+      ("box 0,0 1,1\nobserve msd origin-every=0 max-lag=10\n", 2, "`origin-every` must be at least 1"),
+    ];
+    for &(text, line, message) in &cases {
+      let error = text.parse::<Scenario>().unwrap_err();
+      assert!(error == ScenarioError { line, message: message.to_string() },
+        "{:?} for {:?}", error, text);
+    }
+  }
+}
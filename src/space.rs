@@ -1,30 +1,45 @@
 use std::slice;
 use std::cmp::{PartialOrd, Ord, Ordering};
-use super::{custom_float, FloatOps, Particle, Time};
+use super::{CustomFloat, Event, Particle, Time, Vector};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Collision {
   Free,
   Wall { t: Time, prev: Particle, next: Particle },
+  /// A particle leaving through a periodic boundary, and re-entering
+  /// through the opposite side.
+  Wrap { t: Time, prev: Particle, next: Particle },
+  /// A particle leaving through an absorbing or open boundary,
+  /// after which it is removed from the space.
+  Exit { t: Time, prev: Particle },
   Bounce {
     t: Time,
     prev1: Particle, prev2: Particle,
     next1: Particle, next2: Particle
-  }
+  },
+  /// A particle moving into a neighbouring cell of a `CellList`.
+  /// The particle itself is unchanged, other than by its motion.
+  Cell { t: Time, prev: Particle, next: Particle, cell: (usize, usize) }
 }
 
 impl Collision {
   pub fn t(&self) -> Time {
     match self {
       &Collision::Wall { t, .. } |
-      &Collision::Bounce { t, .. } => t,
-      _ => Time(custom_float::INFINITY),
+      &Collision::Wrap { t, .. } |
+      &Collision::Exit { t, .. } |
+      &Collision::Bounce { t, .. } |
+      &Collision::Cell { t, .. } => t,
+      _ => Time(CustomFloat::INFINITY),
     }
   }
-  pub fn t_mut<'l>(&'l mut self) -> &'l mut Time {
+  pub fn t_mut(&mut self) -> &mut Time {
     match self {
       &mut Collision::Wall { ref mut t, .. } |
-      &mut Collision::Bounce { ref mut t, .. } => t,
+      &mut Collision::Wrap { ref mut t, .. } |
+      &mut Collision::Exit { ref mut t, .. } |
+      &mut Collision::Bounce { ref mut t, .. } |
+      &mut Collision::Cell { ref mut t, .. } => t,
       _ => {
         error!("Collision::t_unsafe called on Collision::Free");
         unreachable!()
@@ -37,24 +52,54 @@ impl Eq for Collision { }
 
 impl PartialOrd for Collision {
   fn partial_cmp(&self, other: &Collision) -> Option<Ordering> {
-    self.t().partial_cmp(&other.t())
+    Some(self.cmp(other))
   }
 }
 impl Ord for Collision {
   fn cmp(&self, other: &Collision) -> Ordering {
-    self.partial_cmp(other)
-        .expect(&format!("Unable to compare collisions: {:?} and {:?}", self, other))
+    self.t().partial_cmp(&other.t())
+        .unwrap_or_else(|| panic!("Unable to compare collisions: {:?} and {:?}", self, other))
   }
 }
 
 pub trait Space: Sized {
-  fn particles(&self) -> slice::Iter<Particle>;
+  fn particles(&self) -> slice::Iter<'_, Particle>;
 
   fn map_particles<F>(&self, f: F) -> Self
   where F: FnMut(&Particle) -> Particle;
 
+  /// The uniform acceleration every particle is subject to.
+  fn gravity(&self) -> &Vector;
+
+  /// Moves every particle forward by `dt`, assuming no collisions occur.
+  fn evolve(&self, dt: Time) -> Self {
+    self.map_particles(|p| p.evolve(dt))
+  }
+
   fn next_collision(&self) -> Collision;
 
-  fn update(&self, collision: &Collision) -> Option<Self>;
+  /// Predicts the earliest collision involving the `i`th particle,
+  /// returning None if it will never collide again.
+  fn predict(&self, i: usize) -> Option<(Time, Event)>;
+
+  /// Computes the collision described by `event` from the current state.
+  /// Returns Collision::Free if the particles involved will not collide.
+  fn collision(&self, event: &Event) -> Collision;
+
+  /// Returns the state just after `collision`, which must be the next
+  /// collision from the current state, or None if it is `Collision::Free`.
+  fn update(&self, collision: &Collision) -> Option<Self>
+  where Self: Clone {
+    let mut next = self.clone();
+    if next.apply(collision) {
+      Some(next)
+    } else {
+      None
+    }
+  }
+
+  /// As `update`, but changes the space in place.
+  /// Returns false, leaving the space unchanged, for `Collision::Free`.
+  fn apply(&mut self, collision: &Collision) -> bool;
 }
 
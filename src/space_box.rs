@@ -1,12 +1,15 @@
-use rand::{Rng};
-use std::cmp::min;
+use std::cmp::{min, Ordering};
 use std::slice;
-use super::{BoundedRand, Bounds, Collision, Particle, Space, SpaceVec, Vector};
+use super::{Boundary, BoundedRand, Bounds, CellList, Collision, CustomFloat, Event, FloatOps,
+  InitError, Lattice, Particle, Restitution, SeededRng, Space, SpaceVec, Time, Vector, Velocities};
+use super::packing::{self, MAX_ATTEMPTS};
+use super::checkpoint::{Checkpoint, CheckpointError, CheckpointReader, CheckpointWriter};
 
 #[derive(Debug, Clone)]
 pub struct SpaceBox {
   space_vec: SpaceVec,
-  bounds: Bounds
+  bounds: Bounds,
+  cells: Option<CellList>
 }
 
 impl SpaceBox {
@@ -17,49 +20,221 @@ impl SpaceBox {
       space_vec.particles().all(|p| bounds.within(p)),
       "bounds must include all particles"
     );
-    SpaceBox { space_vec: space_vec, bounds: bounds }
+    SpaceBox { space_vec, bounds, cells: None }
   }
 
-  pub fn new_random<R: Rng>(rng: &mut R, count: usize, min: Particle, max: Particle) -> SpaceBox {
+  /// Partitions the box into a grid of cells as wide as the largest particle,
+  /// so that collisions are only predicted between neighbouring particles.
+  /// Particles moving between cells are reported as `Collision::Cell`.
+  pub fn with_cells(self) -> SpaceBox {
+    let cells = CellList::new(&self.bounds, self.cell_size(), self.particles());
+    SpaceBox { cells: Some(cells), .. self }
+  }
+
+  /// Places `count` particles at random, without overlaps, with their
+  /// properties drawn between those of `min` and `max`. The box is just
+  /// large enough for particles of the largest radius centred anywhere
+  /// between `min.x` and `max.x`.
+  ///
+  /// Gives up if `MAX_ATTEMPTS` positions for any particle all overlap
+  /// those already placed, as when the box is too small for them.
+  pub fn new_random(rng: &mut SeededRng, count: usize, min: Particle, max: Particle)
+                    -> Result<SpaceBox, InitError> {
     let mut particles = Vec::with_capacity(count);
 
     for i in 0..count {
-      let mut new_p: Particle;
-      loop {
-        new_p = BoundedRand::rand(rng, &min, &max);
-        if ! particles.iter().any(|p: &Particle| p.overlaps(&new_p)) { break; }
+      let new_p = (0..MAX_ATTEMPTS)
+        .map(|_| BoundedRand::rand(rng, &min, &max))
+        .find(|new_p| ! particles.iter().any(|p: &Particle| p.overlaps(new_p)));
+      match new_p {
+        Some(new_p) => particles.push(Particle { id: i, .. new_p }),
+        None => return Err(InitError::Attempts { placed: i, count }),
       }
-      new_p.id = i;
-      particles.push(new_p);
     }
 
     let r_vec = Vector((max.r, max.r));
-    SpaceBox::new(particles, &min.x - &r_vec, &max.x + &r_vec)
+    Ok(SpaceBox::new(particles, &min.x - &r_vec, &max.x + &r_vec))
+  }
+
+  /// Fills `bounds` with particles until they cover `fraction` of its area,
+  /// as nearly as whole particles allow, with their radii, masses and
+  /// velocities drawn between those of `min` and `max`.
+  ///
+  /// Particles are placed at random up to a packing fraction of 0.5, and
+  /// above it, or if random placement gives up, on a triangular lattice
+  /// which is then melted by random displacements.
+  pub fn new_packed(rng: &mut SeededRng, bounds: Bounds, fraction: CustomFloat, min: &Particle,
+                    max: &Particle) -> Result<SpaceBox, InitError> {
+    if !(0. < fraction && fraction < 1.) {
+      return Err(InitError::Invalid(format!("the packing fraction {} is not within (0, 1)", fraction)));
+    } else if min.r.is_nan() || min.r <= 0. {
+      return Err(InitError::Invalid("radii must be positive".to_string()));
+    }
+
+    let particles = packing::draw_covering(rng, min, max, fraction * bounds.area()).into_iter()
+      .enumerate()
+      .map(|(i, p)| Particle { id: i, .. p })
+      .collect();
+    let particles = packing::place(rng, &bounds, &[], particles)?;
+    Ok(SpaceBox::placed(particles, bounds))
+  }
+
+  /// Places a particle like `template` at every site of `lattice` inside
+  /// `bounds`, `spacing` apart, with velocities drawn from `velocities`.
+  pub fn new_lattice(rng: &mut SeededRng, bounds: Bounds, lattice: Lattice, spacing: CustomFloat,
+                     template: &Particle, velocities: Velocities) -> Result<SpaceBox, InitError> {
+    if spacing.partial_cmp(&(2. * template.r)).is_none_or(Ordering::is_lt) {
+      return Err(InitError::Invalid(format!(
+        "a lattice spacing of {} is too small for particles of radius {}", spacing, template.r)));
+    }
+    let mut particles: Vec<Particle> = lattice.sites(&bounds, spacing, template.r).into_iter()
+      .enumerate()
+      .map(|(i, x)| Particle { id: i, x, displacement: Vector((0., 0.)), .. template.clone() })
+      .collect();
+    if particles.is_empty() {
+      return Err(InitError::Invalid(format!("the box has no room for a lattice spaced {} apart", spacing)));
+    }
+    velocities.assign(rng, &mut particles);
+    Ok(SpaceBox::placed(particles, bounds))
+  }
+
+  /// Places particles by random sequential adsorption: one at a time, at
+  /// uniformly random positions which do not overlap those already placed,
+  /// until they cover `coverage` of the area of `bounds`. Their radii and
+  /// masses are drawn between those of `min` and `max`, and their
+  /// velocities from `velocities`.
+  ///
+  /// Gives up if `MAX_ATTEMPTS` positions for any particle all overlap, as
+  /// when the coverage is beyond the jamming limit, which is about 0.547
+  /// for equal disks.
+  pub fn new_adsorbed(rng: &mut SeededRng, bounds: Bounds, coverage: CustomFloat, min: &Particle,
+                      max: &Particle, velocities: Velocities) -> Result<SpaceBox, InitError> {
+    if !(0. < coverage && coverage < 1.) {
+      return Err(InitError::Invalid(format!("the coverage {} is not within (0, 1)", coverage)));
+    } else if min.r.is_nan() || min.r <= 0. {
+      return Err(InitError::Invalid("radii must be positive".to_string()));
+    }
+    let particles = packing::draw_covering(rng, min, max, coverage * bounds.area());
+    let mut particles: Vec<Particle> = packing::place_randomly(rng, &bounds, &[], particles)?
+      .into_iter()
+      .enumerate()
+      .map(|(i, p)| Particle { id: i, .. p })
+      .collect();
+    velocities.assign(rng, &mut particles);
+    Ok(SpaceBox::placed(particles, bounds))
+  }
+
+  /// A box of particles which have already been placed inside `bounds`.
+  fn placed(particles: Vec<Particle>, bounds: Bounds) -> SpaceBox {
+    SpaceBox { space_vec: SpaceVec::new(particles), bounds, cells: None }
+  }
+
+  /// Changes how particles interact with all edges of the box.
+  ///
+  /// # Panics
+  /// - if any particles overlap across a periodic boundary
+  pub fn with_boundary(self, boundary: Boundary) -> SpaceBox {
+    let bounds = self.bounds.clone().with_boundary(boundary);
+    self.with_bounds(bounds)
+  }
+
+  /// Changes how particles interact with each edge of the box.
+  ///
+  /// # Panics
+  /// - if a periodic side is opposite a side which is not periodic
+  /// - if any particles overlap across a periodic boundary
+  pub fn with_sides(self, left: Boundary, right: Boundary, bottom: Boundary, top: Boundary) -> SpaceBox {
+    let bounds = self.bounds.clone().with_sides(left, right, bottom, top);
+    self.with_bounds(bounds)
   }
 
-  pub fn space_vec<'l>(&'l self) -> &'l SpaceVec {
+  /// Makes collisions inelastic, with separate coefficients of restitution
+  /// for collisions between particles and collisions with walls.
+  pub fn with_restitution(self, particles: Restitution, walls: Restitution) -> SpaceBox {
+    SpaceBox {
+      space_vec: self.space_vec.with_restitution(particles),
+      bounds: self.bounds.with_restitution(walls),
+      cells: self.cells
+    }
+  }
+
+  /// Subjects every particle to the uniform acceleration `g`.
+  /// Particles follow exact parabolas, including when predicting
+  /// collisions with walls and crossings between cells.
+  pub fn with_gravity(self, g: Vector) -> SpaceBox {
+    SpaceBox { space_vec: self.space_vec.with_gravity(g), .. self }
+  }
+
+  fn with_bounds(self, bounds: Bounds) -> SpaceBox {
+    assert!(
+      !self.space_vec.particle_pairs().any(|(p1, p2)| {
+        let d = bounds.separation(&p1.x, &p2.x).norm();
+        d < p1.r + p2.r - 1e-5
+      }),
+      "SpaceBox has particles overlapping across its boundary"
+    );
+    let cells = self.cells.as_ref()
+      .map(|_| CellList::new(&bounds, self.cell_size(), self.particles()));
+    SpaceBox { bounds, cells, .. self }
+  }
+
+  fn cell_size(&self) -> CustomFloat {
+    2. * self.particles().fold(0., |r, p| p.r.max(r))
+  }
+
+  pub fn space_vec(&self) -> &SpaceVec {
     &self.space_vec
   }
+
+  pub fn bounds(&self) -> &Bounds {
+    &self.bounds
+  }
+
+  fn particle(&self, i: usize) -> Particle {
+    self.space_vec.particle(i).clone()
+  }
 }
 
 impl Space for SpaceBox {
 
-  fn particles(&self) -> slice::Iter<Particle> {
+  fn particles(&self) -> slice::Iter<'_, Particle> {
     self.space_vec.particles()
   }
 
+  fn gravity(&self) -> &Vector {
+    self.space_vec.gravity()
+  }
+
   fn map_particles<F>(&self, f: F) -> SpaceBox
   where F: FnMut(&Particle) -> Particle {
     SpaceBox {
       space_vec: self.space_vec.map_particles(f),
-      bounds: self.bounds.clone()
+      bounds: self.bounds.clone(),
+      cells: self.cells.clone()
+    }
+  }
+
+  fn evolve(&self, dt: Time) -> SpaceBox {
+    SpaceBox {
+      space_vec: self.space_vec.evolve(dt),
+      bounds: self.bounds.clone(),
+      cells: self.cells.clone()
     }
   }
 
   fn next_collision(&self) -> Collision {
+    if self.cells.is_some() || self.bounds.periodic() != (false, false) {
+      // cell crossings and periodic images are only considered
+      // when predicting per particle
+      return (0..self.particles().len())
+        .filter_map(|i| self.predict(i))
+        .min_by_key(|&(Time(t), event)| (FloatOps(t), event))
+        .map_or(Collision::Free, |(_, event)| self.collision(&event));
+    }
+
     let inter_particle_coll = self.space_vec.next_collision();
-    let wall_coll = self.particles()
-      .map(|p| self.bounds.next_collision(p))
+    let wall_coll = (0..self.particles().len())
+      .map(|i| self.bounds.next_collision(&self.particle(i), self.gravity()))
       .min()
       .unwrap_or(Collision::Free);
 
@@ -73,43 +248,117 @@ impl Space for SpaceBox {
     first_coll
   }
 
-  fn update(&self, collision: &Collision) -> Option<Self> {
-    let space_vec_opt = match collision {
-      &Collision::Wall { t, ref prev, ref next } => {
-        let new_vec: Vec<_> = self.particles().map( |p: &Particle|
-          if p.id == prev.id { next.clone() }
-          else { p.evolve(t) }
-        ).collect();
+  fn predict(&self, i: usize) -> Option<(Time, Event)> {
+    let p = &self.particle(i);
+    let images = self.bounds.images();
+    let bounce = match self.cells {
+      Some(ref cells) => self.space_vec.predict_among(i, cells.neighbours(i), &images),
+      None => self.space_vec.predict_among(i, 0..self.particles().len(), &images)
+    };
+    let wall = match self.bounds.next_collision(p, self.gravity()) {
+      Collision::Free => None,
+      coll => Some((coll.t(), Event::Wall(i)))
+    };
+    let cross = self.cells.as_ref()
+      .and_then(|cells| cells.crossing(i, p, self.gravity()))
+      .map(|(t, _)| (t, Event::Cell(i)));
 
-        Some(SpaceVec::new(new_vec))
+    // on a tie, prefer the inter-particle collision as next_collision does
+    bounce.into_iter().chain(wall).chain(cross)
+      .min_by_key(|&(Time(t), event)| (FloatOps(t), event))
+  }
+
+  fn collision(&self, event: &Event) -> Collision {
+    match *event {
+      Event::Wall(i) => self.bounds.next_collision(&self.particle(i), self.gravity()),
+      Event::Bounce(..) => self.space_vec.collision_among(event, &self.bounds.images()),
+      Event::Cell(i) => {
+        let p = &self.particle(i);
+        match self.cells.as_ref().and_then(|cells| cells.crossing(i, p, self.gravity())) {
+          Some((t, cell)) => Collision::Cell {
+            t,
+            prev: p.clone(),
+            next: p.evolve_under(t, self.gravity()),
+            cell
+          },
+          None => Collision::Free
+        }
+      }
+    }
+  }
+
+  fn apply(&mut self, collision: &Collision) -> bool {
+    match collision {
+      &Collision::Wall { t, ref next, .. } => {
+        self.space_vec.elapse(t);
+        self.space_vec.replace(next);
       },
-      _ => self.space_vec
-        .update(collision)
-    };
+      &Collision::Wrap { t, ref next, .. } => {
+        self.space_vec.elapse(t);
+        let i = self.space_vec.replace(next);
+        if let Some(ref mut cells) = self.cells {
+          let cell = cells.locate(&next.x);
+          cells.move_particle(i, cell);
+        }
+      },
+      &Collision::Cell { t, ref next, cell, .. } => {
+        self.space_vec.elapse(t);
+        let i = self.space_vec.replace(next);
+        if let Some(ref mut cells) = self.cells {
+          cells.move_particle(i, cell);
+        }
+      },
+      &Collision::Exit { t, ref prev } => {
+        self.space_vec.elapse(t);
+        let i = self.space_vec.remove(prev.id);
+        if let Some(ref mut cells) = self.cells {
+          cells.remove_particle(i);
+        }
+      },
+      &Collision::Bounce { .. } |
+      &Collision::Free => return self.space_vec.apply(collision)
+    }
+    true
+  }
+}
 
-    space_vec_opt.map(|sv| SpaceBox { space_vec: sv, bounds: self.bounds.clone() } )
+impl Checkpoint for SpaceBox {
+  fn save_to(&self, w: &mut CheckpointWriter) {
+    self.space_vec.save_to(w);
+    self.bounds.save_to(w);
+    match self.cells {
+      Some(ref cells) => cells.save_to(w),
+      None => { w.line("no-cells"); },
+    }
+  }
+
+  fn load_from(r: &mut CheckpointReader) -> Result<SpaceBox, CheckpointError> {
+    let space_vec = SpaceVec::load_from(r)?;
+    let bounds = Bounds::load_from(r)?;
+    let cells = if r.peek() == Some("no-cells") {
+      r.expect("no-cells")?;
+      None
+    } else {
+      let cells = CellList::load_from(r)?;
+      if cells.particle_count() != space_vec.particles().len() {
+        return Err(CheckpointError::Malformed(format!(
+          "cells hold {} particles, but the space has {}",
+          cells.particle_count(), space_vec.particles().len())));
+      }
+      Some(cells)
+    };
+    Ok(SpaceBox { space_vec, bounds, cells })
   }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
-  use super::super::{Collision, Particle, Space, Vector, SpaceVec, Bounds, Time};
-
-  const P1: Particle = Particle {
-    id: 0,
-    x: Vector((0., 0.)),
-    v: Vector((0., 1.)),
-    r: 1.,
-    m: 1.
-  };
-  const P2: Particle = Particle {
-    id: 0,
-    x: Vector((1., 3.)),
-    v: Vector((-1., 0.)),
-    r: 1.,
-    m: 1.
-  };
+  use super::super::{Boundary, Collision, CustomFloat, FloatOps, Particle, Restitution, Space,
+    Vector, Time};
+
+  const P1: Particle = Particle::new(0, Vector((0., 0.)), Vector((0., 1.)), 1., 1.);
+  const P2: Particle = Particle::new(0, Vector((1., 3.)), Vector((-1., 0.)), 1., 1.);
   const TOP_RIGHT: Vector = Vector((5., 5.));
   const BOTTOM_LEFT: Vector = Vector((-5., -5.));
 
@@ -118,7 +367,7 @@ mod tests {
     let space_box = SpaceBox::new(
       vec![P1], BOTTOM_LEFT, TOP_RIGHT);
 
-    let l = ((TOP_RIGHT.0).1 - P1.r);
+    let l = (TOP_RIGHT.0).1 - P1.r;
     let expected_collision = Collision::Wall {
         t: Time( l / (P1.v.0).1),
         prev: P1,
@@ -135,6 +384,85 @@ mod tests {
       expected_collision);
   }
 
+  #[test]
+  fn single_particle_hits_inelastic_wall() {
+    let space_box = SpaceBox::new(vec![P1], BOTTOM_LEFT, TOP_RIGHT)
+      .with_restitution(Restitution::elastic(), Restitution::new(0.5, 0.));
+
+    match space_box.next_collision() {
+      Collision::Wall { next, .. } => assert!(next.v == Vector((0., -0.5))),
+      collision => panic!("{:?} was not a wall collision", collision)
+    }
+  }
+
+  #[test]
+  fn particle_bounces_under_gravity() {
+    let p = Particle { v: Vector((0., 0.)), .. P1 };
+    let space_box = SpaceBox::new(vec![p.clone()], BOTTOM_LEFT, TOP_RIGHT)
+      .with_gravity(Vector((0., -1.)));
+
+    // falls 4 units onto the floor, then bounces back up to where it started
+    let fall = (8. as CustomFloat).sqrt();
+    let floor = space_box.next_collision();
+    match floor {
+      Collision::Wall { t: Time(t), ref next, .. } => {
+        assert!(FloatOps(t).close(&FloatOps(fall)));
+        assert!((&next.x - &Vector((0., -4.))).norm() < 1e-10);
+        assert!((&next.v - &Vector((0., fall))).norm() < 1e-10);
+      },
+      _ => panic!("{:?} was not a wall collision", floor)
+    }
+
+    let Time(t) = space_box.update(&floor).unwrap().next_collision().t();
+    assert!(FloatOps(t).close(&FloatOps(2. * fall)));
+  }
+
+  #[test]
+  fn particle_resting_on_floor_stays_above_it() {
+    let p = Particle { x: Vector((0., -4.)), v: Vector((0., 0.)), .. P1 };
+    let mut space_box = SpaceBox::new(vec![p], BOTTOM_LEFT, TOP_RIGHT)
+      .with_gravity(Vector((0., -1.)));
+
+    // touches the floor at once, then hops on it without sinking
+    let floor = space_box.next_collision();
+    assert!(floor.t() == Time(0.), "{:?} was not an immediate collision", floor);
+    for _ in 0..10 {
+      let collision = space_box.next_collision();
+      match collision {
+        Collision::Wall { ref next, .. } => {
+          assert!((next.x.0).1 > -4. - 1e-10, "{:?} sank through the floor", next);
+          assert!((next.v.0).1 > 0., "{:?} did not leave the floor", next);
+        },
+        _ => panic!("{:?} was not a wall collision", collision)
+      }
+      space_box = space_box.update(&collision).unwrap();
+    }
+  }
+
+  #[test]
+  fn resting_particles_gain_at_most_one_hop_of_energy() {
+    use super::super::bounds::RESTING_HOP;
+    use super::super::{ConservationMonitor, OnDrift, SpaceTime};
+
+    // three particles at rest on the floor, which the fourth knocks about
+    let resting = [-3., 0., 3.].iter().enumerate()
+      .map(|(id, &x)| Particle { id, x: Vector((x, -4.)), v: Vector((0., 0.)), .. P1 });
+    let moving = Particle { id: 3, x: Vector((-3., 2.)), v: Vector((1.5, -0.5)), .. P1 };
+    let g = Vector((0., -1.));
+    let space_box = SpaceBox::new(resting.chain(Some(moving)).collect(), BOTTOM_LEFT, TOP_RIGHT)
+      .with_gravity(g);
+    let init = SpaceTime::new(space_box, Time(0.));
+    let mut monitor = ConservationMonitor::new(&init, Vector((0., 0.)))
+      .with_gravity(&init, g)
+      .with_on_drift(OnDrift::Warn);
+
+    for s in init.every(Time(0.5)).take(200) {
+      monitor.check(&s);
+    }
+    let bound = 3. * P1.m * RESTING_HOP / monitor.initial().energy.abs() + 1e-10;
+    assert!(monitor.max_drift().energy <= bound, "{:?} exceeds {}", monitor.max_drift(), bound);
+  }
+
   #[test]
   fn particles_collide_before_wall() {
     let space_box = SpaceBox::new(
@@ -161,4 +489,153 @@ mod tests {
       expected_collision);
   }
 
+  #[test]
+  fn particle_wraps_through_periodic_boundary() {
+    let space_box = SpaceBox::new(
+      vec![P1], BOTTOM_LEFT, TOP_RIGHT).with_boundary(Boundary::Periodic);
+
+    let expected_collision = Collision::Wrap {
+        t: Time((TOP_RIGHT.0).1 / (P1.v.0).1),
+        prev: P1,
+        next: Particle {
+          x: Vector((0., (BOTTOM_LEFT.0).1)),
+          .. P1.clone() },
+    };
+    let collision = space_box.next_collision();
+    assert!(
+      collision == expected_collision,
+      "{:?} did not equal {:?}",
+      collision,
+      expected_collision);
+  }
+
+  #[test]
+  fn particles_collide_across_periodic_boundary() {
+    let p1 = Particle { id: 1, x: Vector((0., 4.)), v: Vector((0., 1.)), r: 0.5, .. P1 };
+    let p2 = Particle { id: 2, x: Vector((0., -4.)), v: Vector((0., -1.)), r: 0.5, .. P1 };
+    let space_box = SpaceBox::new(
+      vec![p1.clone(), p2.clone()], BOTTOM_LEFT, TOP_RIGHT).with_boundary(Boundary::Periodic);
+
+    let expected_collision = Collision::Bounce {
+        t: Time(0.5),
+        prev1: p1.clone(), prev2: p2.clone(),
+        next1: Particle { x: Vector((0., 4.5)), v: Vector((0., -1.)), .. p1 },
+        next2: Particle { x: Vector((0., -4.5)), v: Vector((0., 1.)), .. p2 },
+    };
+    let collision = space_box.next_collision();
+    assert!(
+      collision == expected_collision,
+      "{:?} did not equal {:?}",
+      collision,
+      expected_collision);
+  }
+
+  #[test]
+  fn particle_is_absorbed_by_wall() {
+    let space_box = SpaceBox::new(vec![P1], BOTTOM_LEFT, TOP_RIGHT)
+      .with_sides(Boundary::Reflecting, Boundary::Reflecting, Boundary::Reflecting, Boundary::Absorbing);
+
+    let expected_collision = Collision::Exit {
+        t: Time(((TOP_RIGHT.0).1 - P1.r) / (P1.v.0).1),
+        prev: P1,
+    };
+    let collision = space_box.next_collision();
+    assert!(
+      collision == expected_collision,
+      "{:?} did not equal {:?}",
+      collision,
+      expected_collision);
+    assert!(space_box.update(&collision).unwrap().particles().len() == 0);
+  }
+
+  #[test]
+  fn channel_wraps_along_and_reflects_across() {
+    let p = Particle { v: Vector((1., 1.)), .. P1 };
+    let space_box = SpaceBox::new(vec![p.clone()], BOTTOM_LEFT, TOP_RIGHT)
+      .with_sides(Boundary::Periodic, Boundary::Periodic, Boundary::Reflecting, Boundary::Reflecting);
+
+    let wall = space_box.next_collision();
+    let expected_wall = Collision::Wall {
+      t: Time(4.),
+      prev: p.clone(),
+      next: Particle { x: Vector((4., 4.)), v: Vector((1., -1.)), .. p.clone() }
+    };
+    assert!(wall == expected_wall, "{:?} did not equal {:?}", wall, expected_wall);
+
+    let wrap = space_box.update(&wall).unwrap().next_collision();
+    let expected_wrap = Collision::Wrap {
+      t: Time(1.),
+      prev: Particle { x: Vector((4., 4.)), v: Vector((1., -1.)), .. p.clone() },
+      next: Particle { x: Vector((-5., 3.)), v: Vector((1., -1.)), .. p }
+    };
+    assert!(wrap == expected_wrap, "{:?} did not equal {:?}", wrap, expected_wrap);
+  }
+
+  #[test]
+  fn cells_find_the_same_collisions() {
+    use super::super::{Calendar, FloatOps};
+
+    let min = Particle::new(0, Vector((-10., -10.)), Vector((-1., -1.)), 0.4, 1.);
+    let max = Particle::new(0, Vector((10., 10.)), Vector((1., 1.)), 0.6, 1.);
+    let mut rng = SeededRng::new(567);
+    let mut plain = SpaceBox::new_random(&mut rng, 60, min, max).unwrap();
+    let mut celled = plain.clone().with_cells();
+
+    let (mut t_plain, mut t_celled) = (0., 0.);
+    let mut calendar = Calendar::new(&celled, Time(t_celled));
+    for _ in 0..200 {
+      let expected = plain.next_collision();
+      plain = plain.update(&expected).unwrap();
+      t_plain += expected.t().0;
+
+      let mut collision;
+      loop {
+        let (event, coll) = calendar.pop(&celled, Time(t_celled)).unwrap();
+        celled = celled.update(&coll).unwrap();
+        t_celled += coll.t().0;
+        calendar.reschedule(&celled, Time(t_celled), &event);
+        collision = coll;
+        if let Collision::Cell { .. } = collision { continue; }
+        break;
+      }
+
+      assert!(FloatOps(t_plain).close(&FloatOps(t_celled)), "{} != {}", t_plain, t_celled);
+      match (&expected, &collision) {
+        (Collision::Wall { prev: p1, .. }, Collision::Wall { prev: p2, .. }) =>
+          assert!(p1.id == p2.id),
+        (Collision::Bounce { prev1: p1, prev2: p2, .. },
+         Collision::Bounce { prev1: q1, prev2: q2, .. }) =>
+          assert!(p1.id == q1.id && p2.id == q2.id),
+        _ => panic!("{:?} did not match {:?}", expected, collision)
+      }
+    }
+  }
+
+  #[test]
+  fn applied_collisions_match_updated_states() {
+    use super::super::Calendar;
+
+    let min = Particle::new(0, Vector((-10., -10.)), Vector((-1., -1.)), 0.4, 1.);
+    let max = Particle::new(0, Vector((10., 10.)), Vector((1., 1.)), 0.6, 1.);
+    let mut rng = SeededRng::new(890);
+    let mut updated = SpaceBox::new_random(&mut rng, 60, min, max).unwrap()
+      .with_sides(Boundary::Periodic, Boundary::Periodic, Boundary::Reflecting, Boundary::Reflecting)
+      .with_gravity(Vector((0., -0.5)))
+      .with_cells();
+    let mut applied = updated.clone();
+
+    let mut now = Time(0.);
+    let mut calendar = Calendar::new(&applied, now);
+    for _ in 0..300 {
+      let (event, coll) = calendar.pop(&applied, now).unwrap();
+      updated = updated.update(&coll).unwrap();
+      applied.apply(&coll);
+      now = Time(now.0 + coll.t().0);
+      calendar.reschedule(&applied, now, &event);
+    }
+
+    for (p, q) in applied.particles().zip(updated.particles()) {
+      assert!(p == q, "{:?} did not equal {:?}", p, q);
+    }
+  }
 }
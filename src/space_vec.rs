@@ -1,22 +1,167 @@
+use std::cmp::{max, min};
+use std::collections::BTreeMap;
 use std::slice;
 use super::{Collision, Combination2, Combination2Iter,
-  FloatOps, Particle, Space, Time };
+  Event, FloatOps, Particle, Restitution, Space, Time, Vector };
+use super::checkpoint::{Checkpoint, CheckpointError, CheckpointReader, CheckpointWriter};
+
+const NO_OFFSET: Vector = Vector((0., 0.));
 
 #[derive(Debug, Clone)]
 pub struct SpaceVec {
   particles: Vec<Particle>,
+  restitution: Restitution,
+  gravity: Vector,
+  // the index of each particle by its id
+  index: BTreeMap<usize, usize>,
 }
 
 impl<'l> SpaceVec {
   pub fn new(ps: Vec<Particle>) -> SpaceVec {
-    let space_vec = SpaceVec { particles: ps };
+    let space_vec = SpaceVec::with_particles(ps, Restitution::elastic(), NO_OFFSET);
     assert!(
-      !space_vec.particle_pairs().any(|pair| (pair.0).overlaps(&pair.1)),
+      !space_vec.particle_pairs().any(|pair| (pair.0).overlaps(pair.1)),
       "SpaceVec initialized with overlapping particles"
     );
     space_vec
   }
 
+  /// Makes collisions between particles inelastic.
+  pub fn with_restitution(self, restitution: Restitution) -> SpaceVec {
+    SpaceVec { restitution, .. self }
+  }
+
+  pub fn restitution(&self) -> &Restitution {
+    &self.restitution
+  }
+
+  /// Subjects every particle to the uniform acceleration `g`,
+  /// so that they move along parabolas rather than straight lines.
+  pub fn with_gravity(self, g: Vector) -> SpaceVec {
+    SpaceVec { gravity: g, .. self }
+  }
+
+  fn with_particles(ps: Vec<Particle>, restitution: Restitution, gravity: Vector) -> SpaceVec {
+    SpaceVec {
+      index: ps.iter().enumerate().map(|(i, p)| (p.id, i)).collect(),
+      particles: ps,
+      restitution,
+      gravity,
+    }
+  }
+
+  /// Returns the index of the particle with the given id.
+  ///
+  /// # Panics
+  /// - if there is no such particle
+  pub fn index_of(&self, id: usize) -> usize {
+    *self.index.get(&id)
+      .unwrap_or_else(|| panic!("SpaceVec has no particle with id {}", id))
+  }
+
+  /// Returns the `i`th particle.
+  pub fn particle(&self, i: usize) -> &Particle {
+    &self.particles[i]
+  }
+
+  /// Moves every particle forward by `t`, in place.
+  pub fn elapse(&mut self, t: Time) {
+    for p in self.particles.iter_mut() {
+      *p = p.evolve_under(t, &self.gravity);
+    }
+  }
+
+  /// Replaces the particle with the same id as `next`, which is its state
+  /// at the current time, and returns its index.
+  pub fn replace(&mut self, next: &Particle) -> usize {
+    let i = self.index_of(next.id);
+    self.particles[i] = next.clone();
+    i
+  }
+
+  /// Removes the particle with the given id and returns its index.
+  /// The indices of the particles after it are shifted down.
+  pub fn remove(&mut self, id: usize) -> usize {
+    let i = self.index_of(id);
+    self.particles.remove(i);
+    self.index = self.particles.iter().enumerate().map(|(i, p)| (p.id, i)).collect();
+    i
+  }
+
+  fn bounce(&self, p1: &Particle, p2: &Particle, t: Time) -> Collision {
+    let (next1, next2) = p1.after_bounce_under(p2, t, &self.restitution, &self.gravity);
+    Collision::Bounce {
+      t,
+      prev1: p1.clone(), prev2: p2.clone(),
+      next1, next2
+    }
+  }
+
+  /// Computes the earliest impact between `p1` and the images of `p2`
+  /// offset by each of `images`, along with the offset of the image it impacts.
+  fn impact_among_images(p1: &Particle, p2: &Particle, images: &[Vector]) -> Option<(Time, Vector)> {
+    images.iter()
+      .filter_map(|offset| {
+        let image = Particle { x: &p2.x + offset, .. p2.clone() };
+        p1.impact_time(&image).map(|t| (t, *offset))
+      })
+      .min_by_key(|&(Time(t), _)| FloatOps(t))
+  }
+
+  /// Predicts the earliest collision between the `i`th particle
+  /// and any of the `candidates`, which are also given by index.
+  /// Each candidate is considered at every offset in `images`, which must
+  /// include the zero vector.
+  pub fn predict_among<I>(&self, i: usize, candidates: I, images: &[Vector]) -> Option<(Time, Event)>
+  where I: IntoIterator<Item=usize> {
+    let p = &self.particles[i];
+    candidates.into_iter()
+      .filter(|&j| j != i)
+      .filter_map(|j| {
+        // order the pair as particle_pairs would
+        let q = &self.particles[j];
+        let (p1, p2) = if i < j { (p, q) } else { (q, p) };
+        SpaceVec::impact_among_images(p1, p2, images)
+          .map(|(t, _)| (t, Event::Bounce(min(i, j), max(i, j))))
+      })
+      .min_by_key(|&(Time(t), event)| (FloatOps(t), event))
+  }
+
+  /// Computes the collision described by `event`, considering every image
+  /// of the second particle as `predict_among` does. The second particle is
+  /// reported at its actual position, rather than that of its image.
+  pub fn collision_among(&self, event: &Event, images: &[Vector]) -> Collision {
+    match event {
+      &Event::Wall(..) |
+      &Event::Cell(..) => unreachable!(),
+      &Event::Bounce(i, j) => {
+        let (p1, p2) = (&self.particles[i], &self.particles[j]);
+        match SpaceVec::impact_among_images(p1, p2, images) {
+          Some((t, offset)) => {
+            let image = Particle { x: &p2.x + &offset, .. p2.clone() };
+            match self.bounce(p1, &image, t) {
+              Collision::Bounce { t, prev1, next1, next2, .. } => Collision::Bounce {
+                t,
+                prev1, prev2: p2.clone(),
+                next1, next2: Particle { x: &next2.x - &offset, .. next2 }
+              },
+              _ => unreachable!()
+            }
+          },
+          None => Collision::Free
+        }
+      }
+    }
+  }
+
+  /// Returns a new SpaceVec with `f` applied to each particle,
+  /// keeping only the particles for which it returns Some.
+  pub fn filter_map_particles<F>(&self, f: F) -> SpaceVec
+  where F: FnMut(&Particle) -> Option<Particle> {
+    let ps = self.particles.iter().filter_map(f).collect();
+    SpaceVec::with_particles(ps, self.restitution, self.gravity)
+  }
+
   // Returns an iterator over all pairs of particles
   // contained in the Space.
   pub fn particle_pairs(&'l self) -> Combination2Iter<'l, Particle> {
@@ -25,17 +170,24 @@ impl<'l> SpaceVec {
 }
 
 impl Space for SpaceVec {
-  fn particles(&self) -> slice::Iter<Particle> {
+  fn particles(&self) -> slice::Iter<'_, Particle> {
     self.particles.iter()
   }
 
+  fn gravity(&self) -> &Vector {
+    &self.gravity
+  }
+
   fn map_particles<F>(&self, f: F) -> SpaceVec
   where F: FnMut(&Particle) -> Particle {
-    SpaceVec {
-      particles: self.particles.iter()
-        .map(f)
-        .collect()
-    }
+    let ps = self.particles.iter().map(f).collect();
+    SpaceVec::with_particles(ps, self.restitution, self.gravity)
+  }
+
+  fn evolve(&self, dt: Time) -> SpaceVec {
+    let mut evolved = self.clone();
+    evolved.elapse(dt);
+    evolved
   }
 
   fn next_collision(&self) -> Collision {
@@ -60,60 +212,72 @@ impl Space for SpaceVec {
     });
 
     if let Some((FloatOps(t), (p1, p2))) = opt_min {
-      let time = Time(t);
-      let (next1, next2) = p1.after_bounce(&p2, time);
-      Collision::Bounce {
-        t: time,
-        prev1: p1.clone(), prev2: p2.clone(),
-        next1: next1, next2: next2
-      }
+      self.bounce(p1, p2, Time(t))
     } else {
       Collision::Free
     }
   }
 
-  fn update(&self, c: &Collision) -> Option<Self> {
+  fn predict(&self, i: usize) -> Option<(Time, Event)> {
+    self.predict_among(i, 0..self.particles.len(), &[NO_OFFSET])
+  }
+
+  fn collision(&self, event: &Event) -> Collision {
+    self.collision_among(event, &[NO_OFFSET])
+  }
+
+  fn apply(&mut self, c: &Collision) -> bool {
     match c {
-      &Collision::Free => None,
-      &Collision::Wall {..} => unreachable!(),
-      &Collision::Bounce { t, ref prev1, ref prev2, ref next1, ref next2 } => {
-        let new_vec: Vec<_> = self.particles.iter().map( move |p: &Particle|
-          if p.id == prev1.id { next1.clone() }
-          else if p.id == prev2.id { next2.clone() }
-          else { p.evolve(t) }
-        ).collect();
-
-        Some(SpaceVec { particles: new_vec })
+      &Collision::Free => false,
+      &Collision::Wall {..} |
+      &Collision::Wrap {..} |
+      &Collision::Exit {..} |
+      &Collision::Cell {..} => unreachable!(),
+      &Collision::Bounce { t, ref next1, ref next2, .. } => {
+        self.elapse(t);
+        self.replace(next1);
+        self.replace(next2);
+        true
       }
     }
   }
 }
 
+impl Checkpoint for SpaceVec {
+  fn save_to(&self, w: &mut CheckpointWriter) {
+    w.line("space").usize(self.particles.len());
+    self.restitution.save_to(w);
+    w.vector(&self.gravity);
+    for i in 0..self.particles.len() {
+      self.particles[i].save_to(w);
+    }
+  }
+
+  fn load_from(r: &mut CheckpointReader) -> Result<SpaceVec, CheckpointError> {
+    r.expect("space")?;
+    let n = r.usize()?;
+    let restitution = Restitution::load_from(r)?;
+    let gravity = r.vector()?;
+    let particles = (0..n).map(|_| Particle::load_from(r)).collect::<Result<_, _>>()?;
+    let space = SpaceVec::with_particles(particles, restitution, gravity);
+    if space.index.len() != n {
+      return Err(CheckpointError::Malformed("particle ids are not unique".to_string()));
+    }
+    Ok(space)
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
-  use super::super::{Particle, Space, Vector};
-
-  const P1: Particle = Particle {
-    id: 1,
-    x: Vector((-2., 0.)),
-    v: Vector((1., 0.)),
-    r: 1.,
-    m: 1.
-  };
-  const P2: Particle = Particle {
-    id: 2,
-    x: Vector((2., 0.)),
-    v: Vector((-1., 0.)),
-    r: 1.,
-    m: 1.
-  };
+  use super::super::{Particle, Vector};
+
+  const P1: Particle = Particle::new(1, Vector((-2., 0.)), Vector((1., 0.)), 1., 1.);
+  const P2: Particle = Particle::new(2, Vector((2., 0.)), Vector((-1., 0.)), 1., 1.);
 
   #[test]
   fn two_particles_yield_one_pair() {
-    let p_box = SpaceVec {
-      particles: vec![P1, P2],
-    };
+    let p_box = SpaceVec::new(vec![P1, P2]);
     let pairs = p_box.particle_pairs().collect::<Vec<_>>();
     assert!(pairs.len() == 1);
   }
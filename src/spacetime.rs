@@ -1,5 +1,9 @@
+use std::io::{self, Read, Write};
+use std::iter;
 use std::mem;
-use super::{Collision, Space, Time};
+use super::{Calendar, Collision, Event, Particle, Space, SpaceBox, Time};
+use super::checkpoint::{read_checkpoint, write_checkpoint, Checkpoint, CheckpointError,
+  CheckpointReader, CheckpointWriter};
 
 
 #[derive(Debug, Clone)]
@@ -18,45 +22,90 @@ impl<S: Space + Clone> SpaceTime<S> {
 
   fn advance(&self, dt: Time) -> SpaceTime<S> {
     SpaceTime::new(
-      self.space.map_particles(|p| p.evolve(dt)),
+      self.space.evolve(dt),
       Time(self.time.0 + dt.0)
     )
   }
 
-  fn update(&self, coll: &Collision) -> SpaceTime<S> {
-    match coll {
-      &Collision::Free => self.clone(),
-
-      &Collision::Wall { t, .. } |
-      &Collision::Bounce { t, .. } =>
-        SpaceTime::new(
-          self.space.update(coll)
-          .expect(&format!("SpaceTime::update unable to update child space")),
-          Time(self.time.0 + t.0)
-        )
+  /// Applies `coll`, which must be the next collision, in place.
+  fn apply(&mut self, coll: &Collision) {
+    if self.space.apply(coll) {
+      self.time.0 += coll.t().0;
     }
   }
 
   pub fn every(self, step: Time) -> SpaceTimeStepIterator<S> {
+    let mut calendar = Calendar::new(&self.space, self.time);
+    let (next_event, next_coll) = match calendar.pop(&self.space, self.time) {
+      Some((event, coll)) => (Some(event), coll),
+      None => (None, Collision::Free)
+    };
     SpaceTimeStepIterator {
-      next_coll: self.space.next_collision(),
       spacetime: self,
-      step: step,
+      calendar,
+      next_event,
+      next_coll,
+      step,
+      seed: None,
+    }
+  }
+
+  /// Iterates over every collision in order, including the crossings
+  /// between cells of a `SpaceBox` with cells.
+  pub fn events(self) -> SpaceTimeEventIterator<S> {
+    let calendar = Calendar::new(&self.space, self.time);
+    SpaceTimeEventIterator {
+      spacetime: self,
+      calendar,
     }
   }
 }
 
+/// A collision yielded by `SpaceTime::events`.
+#[derive(Debug, Clone)]
+pub struct CollisionRecord {
+  /// The absolute time at which the collision occurs.
+  pub time: Time,
+  pub event: Event,
+  pub collision: Collision,
+  /// The particles involved, just before the collision,
+  /// in the order given by `Event::particles`.
+  pub before: Vec<Particle>,
+  /// The same particles just after the collision. Empty if the
+  /// particle was removed.
+  pub after: Vec<Particle>,
+}
+
 
+#[derive(Clone)]
 pub struct SpaceTimeStepIterator<S: Space> {
   spacetime: SpaceTime<S>,
+  calendar: Calendar,
+  next_event: Option<Event>,
   next_coll: Collision,
-  step: Time
+  step: Time,
+  seed: Option<usize>,
 }
 
-impl<S: Space + Clone> Iterator for SpaceTimeStepIterator<S> {
-  type Item = SpaceTime<S>;
+impl<S: Space + Clone> SpaceTimeStepIterator<S> {
+  /// Records the seed of the random initial state, which is saved in
+  /// checkpoints.
+  pub fn with_seed(self, seed: usize) -> SpaceTimeStepIterator<S> {
+    SpaceTimeStepIterator { seed: Some(seed), .. self }
+  }
 
-  fn next(&mut self) -> Option<SpaceTime<S>> {
+  /// Returns the seed given to `with_seed`, if any.
+  pub fn seed(&self) -> Option<usize> {
+    self.seed
+  }
+
+  /// As `next`, but also passes each collision processed, along with the
+  /// absolute time at which it occurs, to `f`.
+  ///
+  /// `f` is given the collisions up to one step ahead of the state returned,
+  /// since they are processed before it is.
+  pub fn next_with<F>(&mut self, mut f: F) -> Option<SpaceTime<S>>
+  where F: FnMut(Time, &Collision) {
     match self.next_coll {
       Collision::Free => {
         let mut spacetime_next = self.spacetime.advance(self.step);
@@ -65,20 +114,19 @@ impl<S: Space + Clone> Iterator for SpaceTimeStepIterator<S> {
       },
 
       Collision::Wall { .. } |
-      Collision::Bounce { .. } => {
+      Collision::Wrap { .. } |
+      Collision::Exit { .. } |
+      Collision::Bounce { .. } |
+      Collision::Cell { .. } => {
         let to_return = self.spacetime.clone();
 
         let mut dt_step = self.step;
         let mut dt_coll = self.next_coll.t();
 
         while dt_coll.lt(&dt_step) {
-          let spacetime_next = self.spacetime.update(&self.next_coll);
           dt_step.0 -= dt_coll.0;
-
-          self.next_coll = spacetime_next.space.next_collision();
+          self.process_collision(&mut f);
           dt_coll = self.next_coll.t();
-
-          self.spacetime = spacetime_next;
         }
         self.spacetime = self.spacetime.advance(dt_step);
         self.next_coll.t_mut().0 -= dt_step.0;
@@ -87,5 +135,320 @@ impl<S: Space + Clone> Iterator for SpaceTimeStepIterator<S> {
       }
     }
   }
+
+  /// Applies the pending collision, then finds the one after it
+  /// by rescheduling only the particles that changed.
+  fn process_collision<F>(&mut self, f: &mut F)
+  where F: FnMut(Time, &Collision) {
+    let time = Time(self.spacetime.time.0 + self.next_coll.t().0);
+    f(time, &self.next_coll);
+    self.spacetime.apply(&self.next_coll);
+
+    if let Some(ref event) = self.next_event {
+      self.calendar.reschedule(&self.spacetime.space, self.spacetime.time, event);
+    }
+    match self.calendar.pop(&self.spacetime.space, self.spacetime.time) {
+      Some((event, coll)) => {
+        self.next_event = Some(event);
+        self.next_coll = coll;
+      },
+      None => {
+        self.next_event = None;
+        self.next_coll = Collision::Free;
+      }
+    }
+  }
+}
+
+impl SpaceTimeStepIterator<SpaceBox> {
+  /// Saves the state of the iterator to `out`, from which `load` resumes it
+  /// exactly, so that it yields the same states as if it had never stopped.
+  ///
+  /// This includes the pending collision and the calendar of later
+  /// predictions, which could differ by rounding if they were predicted
+  /// afresh. No random numbers are drawn once a simulation has started, so
+  /// the only generator state saved is the seed given to `with_seed`.
+  pub fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+    write_checkpoint(out, self)
+  }
+
+  pub fn load(input: &mut dyn Read) -> Result<SpaceTimeStepIterator<SpaceBox>, CheckpointError> {
+    read_checkpoint(input)
+  }
+}
+
+impl<S: Space + Checkpoint> Checkpoint for SpaceTime<S> {
+  fn save_to(&self, w: &mut CheckpointWriter) {
+    w.line("time");
+    self.time.save_to(w);
+    self.space.save_to(w);
+  }
+
+  fn load_from(r: &mut CheckpointReader) -> Result<SpaceTime<S>, CheckpointError> {
+    r.expect("time")?;
+    let time = Time::load_from(r)?;
+    Ok(SpaceTime { time, space: S::load_from(r)? })
+  }
+}
+
+impl<S: Space + Checkpoint> Checkpoint for SpaceTimeStepIterator<S> {
+  fn save_to(&self, w: &mut CheckpointWriter) {
+    w.line("seed");
+    match self.seed {
+      Some(seed) => w.usize(seed),
+      None => w.word("none"),
+    };
+    w.line("step");
+    self.step.save_to(w);
+    self.spacetime.save_to(w);
+    w.line("next");
+    match self.next_event {
+      Some(ref event) => event.save_to(w),
+      None => { w.word("none"); },
+    }
+    self.next_coll.save_to(w);
+    self.calendar.save_to(w);
+  }
+
+  fn load_from(r: &mut CheckpointReader) -> Result<SpaceTimeStepIterator<S>, CheckpointError> {
+    r.expect("seed")?;
+    let seed = if r.peek() == Some("none") {
+      r.expect("none")?;
+      None
+    } else {
+      Some(r.usize()?)
+    };
+    r.expect("step")?;
+    let step = Time::load_from(r)?;
+    let spacetime: SpaceTime<S> = SpaceTime::load_from(r)?;
+    r.expect("next")?;
+    let next_event = if r.peek() == Some("none") {
+      r.expect("none")?;
+      None
+    } else {
+      Some(Event::load_from(r)?)
+    };
+    let n = spacetime.space.particles().len();
+    let mut expected = match next_event {
+      Some(event) => {
+        let (i, opt_j) = event.particles();
+        if i >= n || opt_j.is_some_and(|j| j >= n) {
+          return Err(CheckpointError::Malformed(format!("{:?} is not among {} particles", event, n)));
+        }
+        let ps = spacetime.space.particles().as_slice();
+        iter::once(i).chain(opt_j).map(|k| ps[k].id).collect()
+      },
+      None => vec![],
+    };
+    let next_coll = Collision::load_from(r)?;
+    let mut ids = match next_coll {
+      Collision::Wall { ref prev, .. } |
+      Collision::Wrap { ref prev, .. } |
+      Collision::Exit { ref prev, .. } |
+      Collision::Cell { ref prev, .. } => vec![prev.id],
+      Collision::Bounce { ref prev1, ref prev2, .. } => vec![prev1.id, prev2.id],
+      Collision::Free => vec![],
+    };
+    ids.sort();
+    expected.sort();
+    if ids != expected {
+      return Err(CheckpointError::Malformed(format!("{:?} does not match the next event", next_coll)));
+    }
+    let calendar = Calendar::load_from(r)?;
+    if calendar.particle_count() != n {
+      return Err(CheckpointError::Malformed(
+        format!("calendar schedules {} particles, not {}", calendar.particle_count(), n)));
+    }
+    Ok(SpaceTimeStepIterator {
+      spacetime,
+      calendar,
+      next_event,
+      next_coll,
+      step,
+      seed,
+    })
+  }
 }
 
+pub struct SpaceTimeEventIterator<S: Space> {
+  spacetime: SpaceTime<S>,
+  calendar: Calendar,
+}
+
+impl<S: Space + Clone> SpaceTimeEventIterator<S> {
+  /// Returns the state just after the last collision yielded.
+  pub fn spacetime(&self) -> &SpaceTime<S> {
+    &self.spacetime
+  }
+}
+
+impl<S: Space + Clone> Iterator for SpaceTimeEventIterator<S> {
+  type Item = CollisionRecord;
+
+  fn next(&mut self) -> Option<CollisionRecord> {
+    let (event, collision) = self.calendar.pop(&self.spacetime.space, self.spacetime.time)?;
+
+    let space = &self.spacetime.space;
+    let ps = space.particles().as_slice();
+    let (i, opt_j) = event.particles();
+    let before = iter::once(i).chain(opt_j)
+      .map(|k| ps[k].evolve_under(collision.t(), space.gravity()))
+      .collect();
+    let after = match collision {
+      Collision::Wall { ref next, .. } |
+      Collision::Wrap { ref next, .. } |
+      Collision::Cell { ref next, .. } => vec![next.clone()],
+      Collision::Bounce { ref next1, ref next2, .. } => vec![next1.clone(), next2.clone()],
+      Collision::Exit { .. } |
+      Collision::Free => vec![],
+    };
+
+    self.spacetime.apply(&collision);
+    self.calendar.reschedule(&self.spacetime.space, self.spacetime.time, &event);
+
+    Some(CollisionRecord {
+      time: self.spacetime.time,
+      event,
+      collision,
+      before,
+      after,
+    })
+  }
+}
+
+impl<S: Space + Clone> Iterator for SpaceTimeStepIterator<S> {
+  type Item = SpaceTime<S>;
+
+  fn next(&mut self) -> Option<SpaceTime<S>> {
+    self.next_with(|_, _| ())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{Boundary, Particle, Restitution, SeededRng, SpaceBox, Vector};
+
+  #[test]
+  fn events_are_yielded_with_particles_before_and_after() {
+    let p1 = Particle::new(0, Vector((-2., 0.)), Vector((1., 0.)), 0.5, 1.);
+    let p2 = Particle::new(1, Vector((2., 0.)), Vector((0., 0.)), 0.5, 1.);
+    let space = SpaceBox::new(vec![p1, p2], Vector((-5., -5.)), Vector((5., 5.)));
+    let records: Vec<CollisionRecord> = SpaceTime::new(space, Time(0.)).events().take(2).collect();
+
+    // the first particle hits the second at t = 3, which hits the wall at t = 5.5
+    let times: Vec<Time> = records.iter().map(|r| r.time).collect();
+    assert!(times == vec![Time(3.), Time(5.5)], "{:?}", times);
+    assert!(records[0].event == Event::Bounce(0, 1), "{:?}", records[0]);
+    let velocities = |ps: &Vec<Particle>| ps.iter().map(|p| p.v).collect::<Vec<_>>();
+    assert!(
+      velocities(&records[0].before) == vec![Vector((1., 0.)), Vector((0., 0.))] &&
+      velocities(&records[0].after) == vec![Vector((0., 0.)), Vector((1., 0.))],
+      "{:?}", records[0]);
+    assert!(records[1].before[0].x == Vector((4.5, 0.)), "{:?}", records[1]);
+  }
+
+  #[test]
+  fn steps_match_a_full_rescan_after_every_collision() {
+    let min = Particle::new(0, Vector((-10., -10.)), Vector((-1., -1.)), 0.5, 1.);
+    let max = Particle::new(0, Vector((10., 10.)), Vector((1., 1.)), 0.5, 2.);
+    let mut rng = SeededRng::new(91);
+    let plain = SpaceBox::new_random(&mut rng, 40, min, max).unwrap();
+    let celled = plain.clone()
+      .with_sides(Boundary::Periodic, Boundary::Periodic, Boundary::Reflecting, Boundary::Reflecting)
+      .with_gravity(Vector((0., -0.5)))
+      .with_cells();
+
+    for space in [plain, celled] {
+      let step = Time(0.37);
+      let steps = SpaceTime::new(space.clone(), Time(0.)).every(step);
+      // as the stepping was before the calendar: every collision is found by
+      // a full rescan, and every particle is moved to it
+      let (mut expected, mut next) = (space.clone(), space.next_collision());
+      for s in steps.take(200) {
+        let (actual, wanted) = (format!("{:?}", s.space.particles().collect::<Vec<_>>()),
+                                format!("{:?}", expected.particles().collect::<Vec<_>>()));
+        assert!(actual == wanted, "{} did not equal {}", actual, wanted);
+
+        let mut dt = step;
+        while next.t().lt(&dt) {
+          expected = expected.update(&next).unwrap();
+          dt.0 -= next.t().0;
+          next = expected.next_collision();
+        }
+        expected = expected.evolve(dt);
+        next.t_mut().0 -= dt.0;
+      }
+    }
+  }
+
+  #[test]
+  fn resumed_steps_match_uninterrupted_steps() {
+    let min = Particle::new(0, Vector((-10., -10.)), Vector((-1., -1.)), 0.5, 1.);
+    let max = Particle::new(0, Vector((10., 10.)), Vector((1., 1.)), 0.5, 2.);
+    let mut rng = SeededRng::new(5678);
+    // dense enough that most steps hold many collisions, and cell crossings
+    // and wraps tie with them
+    let space = SpaceBox::new_random(&mut rng, 150, min, max).unwrap()
+      .with_sides(Boundary::Periodic, Boundary::Periodic, Boundary::Periodic, Boundary::Periodic)
+      .with_restitution(Restitution::new(0.9, 0.1), Restitution::elastic())
+      .with_cells();
+    let mut steps = SpaceTime::new(space, Time(0.)).every(Time(0.37)).with_seed(5678);
+    for _ in 0..4 {
+      for _ in steps.by_ref().take(25) {}
+
+      let mut checkpoint = Vec::new();
+      steps.save(&mut checkpoint).unwrap();
+      let resumed = SpaceTimeStepIterator::load(&mut &checkpoint[..]).unwrap();
+      assert!(resumed.seed() == Some(5678), "{:?} did not equal {:?}", resumed.seed(), Some(5678));
+
+      // compared by their debug output, so that every bit of every field matters
+      let expected: Vec<String> = steps.clone().take(50).map(|s| format!("{:?}", s)).collect();
+      let actual: Vec<String> = resumed.take(50).map(|s| format!("{:?}", s)).collect();
+      assert!(actual == expected, "{:?} did not equal {:?}", actual, expected);
+    }
+  }
+
+  #[test]
+  fn checkpoint_with_cells_for_other_particles_is_rejected() {
+    let min = Particle::new(0, Vector((-10., -10.)), Vector((-1., -1.)), 0.5, 1.);
+    let max = Particle::new(0, Vector((10., 10.)), Vector((1., 1.)), 0.5, 2.);
+    let mut rng = SeededRng::new(5678);
+    let space = SpaceBox::new_random(&mut rng, 10, min, max).unwrap().with_cells();
+    let mut checkpoint = Vec::new();
+    SpaceTime::new(space, Time(0.)).every(Time(1.)).save(&mut checkpoint).unwrap();
+
+    // the last particle is dropped from the space, but not from its cells
+    let text = String::from_utf8(checkpoint).unwrap().replace("space 10", "space 9");
+    let bounds = text.find("\nbounds").unwrap();
+    let last = text[..bounds].rfind("\nparticle").unwrap();
+    let text = format!("{}{}", &text[..last], &text[bounds..]);
+    match SpaceTimeStepIterator::load(&mut text.as_bytes()) {
+      Err(CheckpointError::Malformed(ref msg)) if msg.starts_with("cells hold") => (),
+      Err(e) => panic!("unexpected error: {}", e),
+      Ok(_) => panic!("checkpoint was accepted"),
+    }
+  }
+
+  #[test]
+  fn checkpoint_with_a_collision_for_another_particle_is_rejected() {
+    let min = Particle::new(0, Vector((-10., -10.)), Vector((-1., -1.)), 0.5, 1.);
+    let max = Particle::new(0, Vector((10., 10.)), Vector((1., 1.)), 0.5, 2.);
+    let mut rng = SeededRng::new(5678);
+    let space = SpaceBox::new_random(&mut rng, 10, min, max).unwrap();
+    let mut checkpoint = Vec::new();
+    SpaceTime::new(space, Time(0.)).every(Time(1.)).save(&mut checkpoint).unwrap();
+
+    // the first particle of the pending collision is given an unknown id
+    let text = String::from_utf8(checkpoint).unwrap();
+    let start = text.rfind("\ncollision").unwrap();
+    let id = start + text[start..].find("\nparticle ").unwrap() + "\nparticle ".len();
+    let end = id + text[id..].find(' ').unwrap();
+    let text = format!("{}{}{}", &text[..id], 1, &text[end..]);
+    match SpaceTimeStepIterator::load(&mut text.as_bytes()) {
+      Err(CheckpointError::Malformed(ref msg)) if msg.ends_with("does not match the next event") => (),
+      Err(e) => panic!("unexpected error: {}", e),
+      Ok(_) => panic!("checkpoint was accepted"),
+    }
+  }
+}
@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use super::{palette, Bounds, Colouring, CustomFloat, Particle, Space, SpaceBox, SpaceTime, Vector};
+
+/// Draws states as SVG images, with the box as a rectangle and every
+/// particle as a circle of its exact radius, optionally with an arrow
+/// showing its velocity.
+///
+/// Coordinates are those of the simulation, with y upwards, and the image
+/// is `width` pixels wide when displayed. Particles crossing a periodic side
+/// are also drawn across the opposite side.
+#[derive(Debug, Clone)]
+pub struct SvgWriter {
+  width: CustomFloat,
+  colouring: Colouring,
+  arrows: Option<CustomFloat>,
+  seed: Option<usize>,
+  // the palette, as SVG colours
+  fills: Vec<String>,
+}
+
+impl SvgWriter {
+  pub fn new(width: CustomFloat) -> SvgWriter {
+    let fills = palette().iter()
+      .map(|&[r, g, b]| format!("#{:02x}{:02x}{:02x}", r, g, b))
+      .collect();
+    SvgWriter { width, colouring: Colouring::Id, arrows: None, seed: None, fills }
+  }
+
+  pub fn with_colouring(self, colouring: Colouring) -> SvgWriter {
+    SvgWriter { colouring, .. self }
+  }
+
+  /// Draws an arrow from the centre of each particle to where it would be
+  /// after `time`, if it kept its velocity.
+  pub fn with_velocities(self, time: CustomFloat) -> SvgWriter {
+    SvgWriter { arrows: Some(time), .. self }
+  }
+
+  /// Records the seed of the random initial state in the description of
+  /// the image.
+  pub fn with_seed(self, seed: usize) -> SvgWriter {
+    SvgWriter { seed: Some(seed), .. self }
+  }
+
+  /// Writes a single state as an SVG image.
+  pub fn snapshot<W: Write>(&self, out: &mut W, s: &SpaceTime<SpaceBox>) -> io::Result<()> {
+    let bounds = s.space.bounds();
+    self.open(out, bounds)?;
+    for p in s.space.particles() {
+      let Vector((x, y)) = p.x;
+      writeln!(out, r#"<circle cx="{}" cy="{}" r="{}" fill="{}"/>"#,
+               x, y, p.r, self.fill(p))?;
+      if let Some(time) = self.arrows {
+        let Vector((x2, y2)) = &p.x + &p.v.scale(time);
+        writeln!(out, r#"<line class="v" x1="{}" y1="{}" x2="{}" y2="{}"/>"#, x, y, x2, y2)?;
+      }
+    }
+    self.close(out, bounds)
+  }
+
+  /// Writes a sequence of states as an animated SVG, which loops forever,
+  /// showing each state for `frame_time` seconds.
+  ///
+  /// Particles are matched between states by their ids, and are hidden
+  /// in states they have been removed from. The box is that of the first
+  /// state.
+  ///
+  /// # Panics
+  /// - if there are no states
+  pub fn animation<W: Write>(&self, out: &mut W, states: &[SpaceTime<SpaceBox>],
+                             frame_time: CustomFloat) -> io::Result<()> {
+    assert!(!states.is_empty(), "an animation needs at least one state");
+    let mut frames: BTreeMap<usize, Vec<Option<&Particle>>> = BTreeMap::new();
+    for (i, s) in states.iter().enumerate() {
+      for p in s.space.particles() {
+        frames.entry(p.id).or_insert_with(|| vec![None; states.len()])[i] = Some(p);
+      }
+    }
+
+    let bounds = states[0].space.bounds();
+    self.open(out, bounds)?;
+    let dur = states.len() as CustomFloat * frame_time;
+    // each value is shown for an equal share of the duration, without tweening
+    let animate = |out: &mut W, attribute: &str, values: Vec<String>| writeln!(
+      out, r#"  <animate attributeName="{}" values="{}" dur="{}s" calcMode="discrete" repeatCount="indefinite"/>"#,
+      attribute, values.join(";"), dur);
+
+    for ps in frames.values() {
+      // hidden particles keep their last position, or their first
+      // if they have not yet appeared
+      let first = ps.iter().flat_map(|p| p.iter()).next().unwrap();
+      let mut shown = Vec::with_capacity(ps.len());
+      for p in ps {
+        shown.push(p.unwrap_or(shown.last().cloned().unwrap_or(first)));
+      }
+      let coords = |f: &dyn Fn(&Particle) -> CustomFloat| shown.iter().map(|p| f(p).to_string()).collect();
+      let display = ps.iter().map(|p| if p.is_some() { "inline" } else { "none" }.to_string()).collect();
+
+      writeln!(out, r#"<g display="{}">"#, if ps[0].is_some() { "inline" } else { "none" })?;
+      animate(out, "display", display)?;
+      writeln!(out, r#"<circle cx="{}" cy="{}" r="{}" fill="{}">"#,
+               (shown[0].x.0).0, (shown[0].x.0).1, shown[0].r, self.fill(shown[0]))?;
+      animate(out, "cx", coords(&|p| (p.x.0).0))?;
+      animate(out, "cy", coords(&|p| (p.x.0).1))?;
+      if let Colouring::Speed(_) = self.colouring {
+        animate(out, "fill", shown.iter().map(|p| self.fill(p).to_string()).collect())?;
+      }
+      writeln!(out, "</circle>")?;
+
+      if let Some(time) = self.arrows {
+        let head = |p: &Particle| &p.x + &p.v.scale(time);
+        writeln!(out, r#"<line class="v" x1="{}" y1="{}" x2="{}" y2="{}">"#,
+                 (shown[0].x.0).0, (shown[0].x.0).1, (head(shown[0]).0).0, (head(shown[0]).0).1)?;
+        animate(out, "x1", coords(&|p| (p.x.0).0))?;
+        animate(out, "y1", coords(&|p| (p.x.0).1))?;
+        animate(out, "x2", coords(&|p| (head(p).0).0))?;
+        animate(out, "y2", coords(&|p| (head(p).0).1))?;
+        writeln!(out, "</line>")?;
+      }
+      writeln!(out, "</g>")?;
+    }
+    self.close(out, bounds)
+  }
+
+  fn fill(&self, p: &Particle) -> &str {
+    &self.fills[self.colouring.index(p) as usize]
+  }
+
+  /// Starts the image, up to the group of particles.
+  fn open<W: Write>(&self, out: &mut W, bounds: &Bounds) -> io::Result<()> {
+    let Vector((x0, y0)) = *bounds.bottom_left();
+    let Vector((wx, wy)) = bounds.top_right() - bounds.bottom_left();
+    // strokes are a pixel wide when displayed
+    let stroke = wx / self.width;
+
+    writeln!(out, r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+             self.width, self.width * wy / wx, x0, -y0 - wy, wx, wy)?;
+    if let Some(seed) = self.seed {
+      writeln!(out, "<desc>seed {}</desc>", seed)?;
+    }
+    writeln!(out, "<defs>")?;
+    writeln!(out, r#"<clipPath id="box"><rect x="{}" y="{}" width="{}" height="{}"/></clipPath>"#, x0, y0, wx, wy)?;
+    writeln!(out, r#"<marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="6" markerHeight="6" orient="auto"><path d="M 0 0 L 10 5 L 0 10 z"/></marker>"#)?;
+    writeln!(out, r#"<style>.v {{ stroke: black; stroke-width: {}; marker-end: url(#arrow) }}</style>"#, stroke)?;
+    writeln!(out, "</defs>")?;
+    // flip y, so that it is upwards
+    writeln!(out, r#"<g transform="scale(1 -1)">"#)?;
+    writeln!(out, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="white" stroke="black" stroke-width="{}"/>"#,
+             x0, y0, wx, wy, stroke)?;
+    writeln!(out, r#"<g clip-path="url(#box)">"#)?;
+    writeln!(out, r#"<g id="particles">"#)
+  }
+
+  /// Ends the group of particles, repeats it for each periodic image,
+  /// and ends the image.
+  fn close<W: Write>(&self, out: &mut W, bounds: &Bounds) -> io::Result<()> {
+    writeln!(out, "</g>")?;
+    for offset in bounds.images().iter().skip(1) {
+      let Vector((x, y)) = *offset;
+      writeln!(out, r##"<use xlink:href="#particles" x="{}" y="{}"/>"##, x, y)?;
+    }
+    writeln!(out, "</g>\n</g>\n</svg>")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{Particle, SpaceBox, SpaceTime, Time, Vector};
+
+  #[test]
+  fn snapshot_draws_box_circles_and_arrows() {
+    let p = Particle::new(0, Vector((1., 2.)), Vector((0.5, -1.)), 0.75, 1.);
+    let space = SpaceBox::new(vec![p], Vector((0., 0.)), Vector((4., 3.)));
+    let mut out = Vec::new();
+    SvgWriter::new(400.).with_velocities(2.)
+      .snapshot(&mut out, &SpaceTime::new(space, Time(0.))).unwrap();
+    let svg = String::from_utf8(out).unwrap();
+
+    for expected in &[
+      r#"width="400" height="300" viewBox="0 -3 4 3""#,
+      r#"<rect x="0" y="0" width="4" height="3" fill="white""#,
+      r#"<circle cx="1" cy="2" r="0.75""#,
+      r#"<line class="v" x1="1" y1="2" x2="2" y2="0"/>"#,
+    ] {
+      assert!(svg.contains(expected), "{} did not contain {}", svg, expected);
+    }
+    assert!(!svg.contains("<use"), "{}", svg);
+  }
+
+  #[test]
+  fn animation_hides_removed_particles() {
+    let p1 = Particle::new(0, Vector((1., 1.)), Vector((1., 0.)), 0.5, 1.);
+    let p2 = Particle::new(1, Vector((3., 2.)), Vector((0., 1.)), 0.5, 1.);
+    let both = SpaceBox::new(vec![p1.clone(), p2], Vector((0., 0.)), Vector((4., 3.)));
+    let one = SpaceBox::new(vec![p1.evolve(Time(1.))], Vector((0., 0.)), Vector((4., 3.)));
+    let states = vec![SpaceTime::new(both, Time(0.)), SpaceTime::new(one, Time(1.))];
+    let mut out = Vec::new();
+    SvgWriter::new(400.).animation(&mut out, &states, 0.5).unwrap();
+    let svg = String::from_utf8(out).unwrap();
+
+    for expected in &[
+      r#"<animate attributeName="cx" values="1;2" dur="1s""#,
+      r#"<animate attributeName="display" values="inline;none""#,
+      r#"<animate attributeName="cy" values="2;2""#,
+    ] {
+      assert!(svg.contains(expected), "{} did not contain {}", svg, expected);
+    }
+  }
+}
@@ -17,25 +17,26 @@ impl Vector {
     let &Vector((x1, x2)) = self;
     Vector((a * x1, a * x2))
   }
-}
 
-impl PartialEq for Vector {
-  fn eq(&self, other: &Vector) -> bool {
+  /// The z component of the cross product of the two vectors.
+  pub fn cross(&self, other: &Vector) -> CustomFloat {
     let &Vector((x1, x2)) = self;
     let &Vector((y1, y2)) = other;
-    x1.eq(&y1) && x2.eq(&y2)
+    x1 * y2 - x2 * y1
   }
+}
 
-  fn ne(&self, other: &Vector) -> bool {
+impl PartialEq for Vector {
+  fn eq(&self, other: &Vector) -> bool {
     let &Vector((x1, x2)) = self;
     let &Vector((y1, y2)) = other;
-    x1.ne(&y1) || x2.ne(&y2)
+    x1.eq(&y1) && x2.eq(&y2)
   }
 }
 
 impl Eq for Vector { }
 
-impl<'l> Mul for &'l Vector {
+impl Mul for &Vector {
   type Output = CustomFloat;
   fn mul(self, rhs: &Vector) -> CustomFloat {
     let &Vector(v1) = self;
@@ -44,7 +45,7 @@ impl<'l> Mul for &'l Vector {
   }
 }
 
-impl<'l> Add for &'l Vector {
+impl Add for &Vector {
   type Output = Vector;
   fn add(self, rhs: &Vector) -> Vector {
     let &Vector(v1) = self;
@@ -53,7 +54,7 @@ impl<'l> Add for &'l Vector {
   }
 }
 
-impl<'l> Sub for &'l Vector {
+impl Sub for &Vector {
   type Output = Vector;
   fn sub(self, rhs: &Vector) -> Vector {
     let &Vector(v1) = self;
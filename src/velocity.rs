@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use super::{CustomFloat, Fit, FloatOps, Histogram, Space, SpaceTime, Vector};
+
+/// Histograms of the speeds and velocity components of particles, sampled
+/// from the states of a simulation, for comparison against the 2D
+/// Maxwell–Boltzmann distribution at the measured temperature.
+///
+/// Particles of different masses are compared against a mixture of the
+/// distributions for each mass, weighted by how often each was sampled.
+#[derive(Debug, Clone)]
+pub struct VelocityDistribution {
+  speeds: Histogram,
+  xs: Histogram,
+  ys: Histogram,
+  // the number of samples of particles with each mass
+  masses: BTreeMap<FloatOps, usize>,
+  energy: CustomFloat,
+}
+
+impl VelocityDistribution {
+  /// Bins speeds over `[0, max_speed)`, and velocity components over
+  /// `[-max_speed, max_speed)`, into `bins` bins each.
+  pub fn new(max_speed: CustomFloat, bins: usize) -> VelocityDistribution {
+    VelocityDistribution {
+      speeds: Histogram::new(0., max_speed, bins),
+      xs: Histogram::new(-max_speed, max_speed, bins),
+      ys: Histogram::new(-max_speed, max_speed, bins),
+      masses: BTreeMap::new(),
+      energy: 0.,
+    }
+  }
+
+  /// Adds the velocity of every particle in the state to the histograms.
+  pub fn sample<S: Space>(&mut self, s: &SpaceTime<S>) {
+    for p in s.space.particles() {
+      let Vector((vx, vy)) = p.v;
+      self.speeds.add(p.v.norm());
+      self.xs.add(vx);
+      self.ys.add(vy);
+      *self.masses.entry(FloatOps(p.m)).or_insert(0) += 1;
+      self.energy += 0.5 * p.m * p.v.norm2();
+    }
+  }
+
+  pub fn speeds(&self) -> &Histogram {
+    &self.speeds
+  }
+
+  /// Returns the histograms of the x and y components of velocity.
+  pub fn components(&self) -> (&Histogram, &Histogram) {
+    (&self.xs, &self.ys)
+  }
+
+  /// Returns the temperature measured from the mean kinetic energy of the
+  /// samples, in units where Boltzmann's constant is 1.
+  pub fn temperature(&self) -> CustomFloat {
+    self.energy / self.speeds.total() as CustomFloat
+  }
+
+  /// Compares the speeds against the distribution f(v) = (mv/kT) exp(-mv²/2kT).
+  pub fn speed_fit(&self) -> Fit {
+    self.speeds.compare(|lo, hi| self.mixture(|m, kt| {
+      let cdf = |v: CustomFloat| if v > 0. { 1. - (-m * v * v / (2. * kt)).exp() } else { 0. };
+      cdf(hi) - cdf(lo)
+    }))
+  }
+
+  /// Compares the x and y components of velocity against the normal
+  /// distribution with variance kT/m.
+  pub fn component_fits(&self) -> (Fit, Fit) {
+    let probability = |lo, hi| self.mixture(|m, kt| {
+      let cdf = |v: CustomFloat| 0.5 * (1. + erf(v * (m / (2. * kt)).sqrt()));
+      cdf(hi) - cdf(lo)
+    });
+    (self.xs.compare(probability), self.ys.compare(probability))
+  }
+
+  /// Averages a probability, given as a function of mass and temperature,
+  /// over the masses of the samples.
+  fn mixture<F>(&self, probability: F) -> CustomFloat
+  where F: Fn(CustomFloat, CustomFloat) -> CustomFloat {
+    let kt = self.temperature();
+    let total = self.speeds.total() as CustomFloat;
+    self.masses.iter()
+      .map(|(&FloatOps(m), &count)| probability(m, kt) * count as CustomFloat / total)
+      .sum()
+  }
+}
+
+/// Approximates the error function to within 1.5e-7,
+/// by formula 7.1.26 of Abramowitz and Stegun.
+fn erf(x: CustomFloat) -> CustomFloat {
+  let t = 1. / (1. + 0.3275911 * x.abs());
+  let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 +
+    t * (-1.453152027 + t * 1.061405429))));
+  let y = 1. - poly * (-x * x).exp();
+  if x < 0. { -y } else { y }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{Particle, SeededRng, SpaceBox, SpaceTime, Time, Vector};
+
+  #[test]
+  fn uniform_velocities_thermalize() {
+    let min = Particle::new(0, Vector((-10., -10.)), Vector((-1., -1.)), 0.5, 1.);
+    let max = Particle::new(0, Vector((10., 10.)), Vector((1., 1.)), 0.5, 1.);
+    let mut rng = SeededRng::new(123);
+    let init = SpaceTime::new(SpaceBox::new_random(&mut rng, 60, min, max).unwrap(), Time(0.));
+
+    let mut initial = VelocityDistribution::new(2.5, 10);
+    initial.sample(&init);
+    let mut later = VelocityDistribution::new(2.5, 10);
+    for s in init.every(Time(1.)).skip(100).take(200) {
+      later.sample(&s);
+    }
+
+    let (before, after) = (initial.speed_fit(), later.speed_fit());
+    assert!(after.kl_divergence < before.kl_divergence, "{:?} {:?}", before, after);
+    assert!(after.kl_divergence < 0.01, "{:?}", after);
+    assert!((initial.temperature() - later.temperature()).abs() < 1e-10);
+  }
+}
@@ -0,0 +1,117 @@
+use std::io::{self, Write};
+use super::{Space, SpaceBox, SpaceTime, Vector};
+
+/// Writes states as frames of a trajectory in extended XYZ format, which
+/// OVITO, VMD and ASE can open directly.
+///
+/// Each frame has a line with the number of particles, a header line with
+/// the box as the `Lattice`, its bottom left corner as the `Origin`, the
+/// periodic axes as `pbc` and the time, followed by a line per particle with
+/// its id, position, velocity, radius and mass. Viewers expect three
+/// dimensions, so positions and velocities have a z component of zero,
+/// within a box as deep as the largest particle. Every particle has the
+/// placeholder species `X`.
+pub struct XyzWriter<W: Write> {
+  out: W,
+  frames: usize,
+  seed: Option<usize>,
+}
+
+impl<W: Write> XyzWriter<W> {
+  pub fn new(out: W) -> XyzWriter<W> {
+    XyzWriter { out, frames: 0, seed: None }
+  }
+
+  /// Records the seed of the random initial state as `Seed` in the header
+  /// of every frame.
+  pub fn with_seed(self, seed: usize) -> XyzWriter<W> {
+    XyzWriter { seed: Some(seed), .. self }
+  }
+
+  /// Returns the number of frames written so far.
+  pub fn frames(&self) -> usize {
+    self.frames
+  }
+
+  pub fn into_inner(self) -> W {
+    self.out
+  }
+
+  pub fn write_frame(&mut self, s: &SpaceTime<SpaceBox>) -> io::Result<()> {
+    let bounds = s.space.bounds();
+    let Vector((wx, wy)) = bounds.top_right() - bounds.bottom_left();
+    let Vector((ox, oy)) = *bounds.bottom_left();
+    let depth = 2. * s.space.particles().fold(0., |r, p| p.r.max(r));
+    let depth = if depth > 0. { depth } else { 1. };
+    let flag = |periodic: bool| if periodic { "T" } else { "F" };
+    let (px, py) = bounds.periodic();
+
+    writeln!(self.out, "{}", s.space.particles().len())?;
+    write!(
+      self.out,
+      "Lattice=\"{} 0 0 0 {} 0 0 0 {}\" Origin=\"{} {} {}\" \
+       Properties=species:S:1:id:I:1:pos:R:3:velo:R:3:radius:R:1:mass:R:1 \
+       Time={} pbc=\"{} {} F\"",
+      wx, wy, depth, ox, oy, -depth / 2., s.time.0, flag(px), flag(py))?;
+    match self.seed {
+      Some(seed) => writeln!(self.out, " Seed={}", seed)?,
+      None => writeln!(self.out)?,
+    }
+    for p in s.space.particles() {
+      let Vector((x, y)) = p.x;
+      let Vector((vx, vy)) = p.v;
+      writeln!(self.out, "X {} {} {} 0 {} {} 0 {} {}", p.id, x, y, vx, vy, p.r, p.m)?;
+    }
+
+    self.frames += 1;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::{Boundary, Particle, SeededRng, SpaceBox, SpaceTime, Time, Vector};
+
+  #[test]
+  fn frame_has_lattice_and_particle_properties() {
+    let p = Particle::new(7, Vector((1., -2.)), Vector((0.5, 0.25)), 1.5, 2.);
+    let space = SpaceBox::new(vec![p], Vector((-5., -4.)), Vector((5., 4.)))
+      .with_sides(Boundary::Periodic, Boundary::Periodic, Boundary::Reflecting, Boundary::Reflecting);
+    let mut writer = XyzWriter::new(Vec::new());
+    writer.write_frame(&SpaceTime::new(space, Time(2.5))).unwrap();
+
+    let text = String::from_utf8(writer.into_inner()).unwrap();
+    let expected = "1\n\
+      Lattice=\"10 0 0 0 8 0 0 0 3\" Origin=\"-5 -4 -1.5\" \
+      Properties=species:S:1:id:I:1:pos:R:3:velo:R:3:radius:R:1:mass:R:1 \
+      Time=2.5 pbc=\"T F F\"\n\
+      X 7 1 -2 0 0.5 0.25 0 1.5 2\n";
+    assert!(text == expected, "{:?} did not equal {:?}", text, expected);
+  }
+
+  #[test]
+  fn same_seed_gives_identical_trajectory() {
+    let trajectory = |seed: usize| {
+      let min = Particle::new(0, Vector((-8., -8.)), Vector((-1., -1.)), 0.3, 1.);
+      let max = Particle::new(0, Vector((8., 8.)), Vector((1., 1.)), 0.8, 3.);
+      let mut rng = SeededRng::new(seed);
+      let space = SpaceBox::new_random(&mut rng, 30, min, max).unwrap();
+      let mut writer = XyzWriter::new(Vec::new()).with_seed(rng.seed());
+      for s in SpaceTime::new(space, Time(0.)).every(Time(0.25)).take(40) {
+        writer.write_frame(&s).unwrap();
+      }
+      writer.into_inner()
+    };
+
+    // the headers record the seed, so only the particles are compared
+    let particles = |out: &[u8]| String::from_utf8(out.to_vec()).unwrap().lines()
+      .filter(|line| line.starts_with("X "))
+      .map(|line| line.to_string())
+      .collect::<Vec<_>>();
+    let (first, again, other) = (trajectory(42), trajectory(42), trajectory(43));
+    assert!(first == again, "the same seed gave different trajectories");
+    assert!(particles(&first) != particles(&other), "different seeds gave the same trajectory");
+    assert!(String::from_utf8(first).unwrap().lines().nth(1).unwrap().ends_with(" Seed=42"));
+  }
+}